@@ -0,0 +1,414 @@
+use parking_lot::Mutex;
+use reqwest::Url;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::time::Instant;
+
+use crate::Latest;
+
+/// How long to wait for a webhook to answer a notification before giving up on it.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for a connection to a webhook before giving up on the notification.
+const WEBHOOK_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A rule describing when an alert should fire.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Rule {
+    /// The name of the alert.
+    pub name: String,
+    /// The severity of the alert, passed through to the webhook payload (e.g. `P1`).
+    #[serde(default)]
+    pub severity: Option<String>,
+    /// The metric the rule is evaluated against.
+    pub metric: Metric,
+    /// How the metric is compared against the threshold.
+    pub op: Comparison,
+    /// The threshold the metric is compared against.
+    pub threshold: f64,
+    /// How long the condition must hold before the alert fires.
+    #[serde(rename = "for", default, deserialize_with = "duration")]
+    pub hold: Duration,
+}
+
+/// A metric which an alerting rule can be evaluated against.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// The validator's state, as a number (see the `state` metric).
+    State,
+    /// The validator's uptime, as a percentage.
+    Uptime,
+    /// The number of most-recent consecutive blocks missed by the validator.
+    ConsecutiveMissedBlocks,
+    /// Whether the last update was successful (1) or not (0); not specific to any validator.
+    UpdateSuccess,
+    /// Whether the chain has halted (1) or not (0); not specific to any validator.
+    ChainHalted,
+}
+
+impl Metric {
+    /// Get the name of the metric, as exported to Prometheus.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::State => "state",
+            Metric::Uptime => "uptime",
+            Metric::ConsecutiveMissedBlocks => "consecutive_missed_blocks",
+            Metric::UpdateSuccess => "update_success",
+            Metric::ChainHalted => "chain_halted",
+        }
+    }
+}
+
+/// A comparison between a metric and a threshold.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    #[serde(rename = "<")]
+    Less,
+    #[serde(rename = "<=")]
+    LessOrEqual,
+    #[serde(rename = ">")]
+    Greater,
+    #[serde(rename = ">=")]
+    GreaterOrEqual,
+    #[serde(rename = "==")]
+    Equal,
+    #[serde(rename = "!=")]
+    NotEqual,
+}
+
+impl Comparison {
+    /// Check whether the comparison holds between the value and the threshold.
+    pub fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Less => value < threshold,
+            Comparison::LessOrEqual => value <= threshold,
+            Comparison::Greater => value > threshold,
+            Comparison::GreaterOrEqual => value >= threshold,
+            Comparison::Equal => value == threshold,
+            Comparison::NotEqual => value != threshold,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+        })
+    }
+}
+
+/// Deserialize a human-readable duration like `10m`.
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    humantime::parse_duration(&s).map_err(serde::de::Error::custom)
+}
+
+/// The rules used when webhooks are configured but no rules are, following the recommendations for
+/// an active validator in the README.
+///
+/// There is no rule for a validator not being active, since it would fire for every inactive
+/// validator monitored with `--all-validators`.
+pub fn default_rules() -> Vec<Rule> {
+    let rule = |name: &str, severity: &str, metric, op, threshold, hold: u64| Rule {
+        name: name.to_string(),
+        severity: Some(severity.to_string()),
+        metric,
+        op,
+        threshold,
+        hold: Duration::from_secs(hold),
+    };
+    use {Comparison::*, Metric::*};
+    vec![
+        rule("ValidatorSlashed", "P0", State, Greater, 3.0, 0),
+        rule("UpdateFailing", "P1", UpdateSuccess, Equal, 0.0, 600),
+        rule("ChainHalted", "P1", ChainHalted, Equal, 1.0, 0),
+        rule(
+            "ValidatorDown",
+            "P1",
+            ConsecutiveMissedBlocks,
+            Greater,
+            120.0,
+            0,
+        ),
+        rule("UptimeCritical", "P1", Uptime, Less, 95.0, 0),
+        rule(
+            "ValidatorMissingBlocks",
+            "P2",
+            ConsecutiveMissedBlocks,
+            Greater,
+            12.0,
+            0,
+        ),
+        rule("UptimeLow", "P2", Uptime, Less, 99.0, 0),
+    ]
+}
+
+/// The configuration of the alerting subsystem.
+#[derive(Debug, Clone, Default)]
+pub struct Alerting {
+    /// The rules to evaluate.
+    pub rules: Vec<Rule>,
+    /// The webhooks to notify when an alert fires or resolves.
+    pub webhooks: Vec<Url>,
+    /// The name of the network the alerts are about, if it is one of several.
+    pub network: Option<String>,
+}
+
+/// The alerting engine, which evaluates rules after each update and notifies webhooks when alerts
+/// fire or resolve.
+#[derive(Debug, Clone)]
+pub struct Alerter {
+    /// The state of each alert, keyed by rule name and subject (validator identity, if any).
+    alerts: Arc<Mutex<HashMap<(String, Option<String>), Alert>>>,
+    /// The HTTP client used to call webhooks.
+    http: reqwest::Client,
+}
+
+impl Default for Alerter {
+    /// Make an alerting engine with no alerts yet, whose webhook calls are given up on after
+    /// [`WEBHOOK_TIMEOUT`], so that a webhook which never answers doesn't leave calls hanging.
+    fn default() -> Self {
+        Self {
+            alerts: Arc::default(),
+            // Building a client only fails if TLS can't be initialized, which `Client::new` also
+            // panics on:
+            http: reqwest::Client::builder()
+                .connect_timeout(WEBHOOK_CONNECT_TIMEOUT)
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()
+                .expect("can build an HTTP client"),
+        }
+    }
+}
+
+/// The state of a single alert, whose condition currently holds.
+#[derive(Debug, Clone)]
+struct Alert {
+    /// When the condition started to hold.
+    pending_since: Instant,
+    /// The wall-clock time at which the condition started to hold.
+    started_at: SystemTime,
+    /// Whether the alert has fired (i.e. the condition has held for long enough).
+    firing: bool,
+}
+
+/// The JSON payload sent to webhooks.
+#[derive(Serialize, Debug, Clone)]
+pub struct Notification {
+    /// Either `firing` or `resolved`.
+    pub status: Status,
+    /// The name of the rule.
+    pub alert: String,
+    /// The severity of the rule, if any.
+    pub severity: Option<String>,
+    /// The validator the alert is about, if any.
+    pub validator: Option<String>,
+    /// The on-chain name of the validator, if known.
+    pub name: Option<String>,
+    /// The operator-supplied alias of the validator, if any.
+    pub alias: Option<String>,
+    /// The network the alert is about, if it is one of several.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    /// The metric the rule is evaluated against.
+    pub metric: Metric,
+    /// The comparison made against the threshold.
+    pub op: String,
+    /// The threshold of the rule.
+    pub threshold: f64,
+    /// The latest value of the metric.
+    pub value: f64,
+    /// When the condition started to hold, in RFC 3339 format.
+    pub started_at: String,
+    /// A human-readable summary of the alert.
+    pub summary: String,
+}
+
+/// Whether an alert is firing or has resolved.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Firing,
+    Resolved,
+}
+
+impl Alerter {
+    /// Evaluate all the rules against the latest info, update success and chain halt (if known),
+    /// and notify each webhook of every alert which fired or resolved.
+    pub fn evaluate(
+        &self,
+        alerting: &Alerting,
+        success: bool,
+        chain_halted: Option<bool>,
+        info: &[Latest],
+    ) {
+        if alerting.webhooks.is_empty() {
+            return;
+        }
+
+        let mut notifications = Vec::new();
+        {
+            let mut alerts = self.alerts.lock();
+            for rule in alerting.rules.iter() {
+                if rule.metric == Metric::UpdateSuccess {
+                    let value = f64::from(u8::from(success));
+                    notifications.extend(transition(
+                        &mut alerts,
+                        alerting,
+                        rule,
+                        None,
+                        Some(value),
+                    ));
+                } else if rule.metric == Metric::ChainHalted {
+                    let value = chain_halted.map(|halted| f64::from(u8::from(halted)));
+                    notifications.extend(transition(&mut alerts, alerting, rule, None, value));
+                } else {
+                    for latest in info.iter() {
+                        let value = match rule.metric {
+                            Metric::State => latest.state_number().map(f64::from),
+                            Metric::Uptime => latest.uptime_percent(),
+                            Metric::ConsecutiveMissedBlocks => {
+                                latest.consecutive_missed_blocks().map(|n| n as f64)
+                            }
+                            Metric::UpdateSuccess | Metric::ChainHalted => unreachable!(),
+                        };
+                        notifications.extend(transition(
+                            &mut alerts,
+                            alerting,
+                            rule,
+                            Some(latest),
+                            value,
+                        ));
+                    }
+                }
+            }
+
+            // Forget about alerts for rules or validators which no longer exist, without notifying,
+            // since there is nothing to say about whether they resolved:
+            alerts.retain(|(name, validator), _| {
+                alerting.rules.iter().any(|rule| &rule.name == name)
+                    && validator.as_ref().map_or(true, |validator| {
+                        info.iter()
+                            .any(|latest| &latest.identity().to_string() == validator)
+                    })
+            });
+        }
+
+        for notification in notifications {
+            match notification.status {
+                Status::Firing => {
+                    warn!(alert = %notification.alert, validator = ?notification.validator, "{}", notification.summary)
+                }
+                Status::Resolved => {
+                    info!(alert = %notification.alert, validator = ?notification.validator, "{}", notification.summary)
+                }
+            }
+            for webhook in alerting.webhooks.iter() {
+                tokio::spawn(notify(
+                    self.http.clone(),
+                    webhook.clone(),
+                    notification.clone(),
+                ));
+            }
+        }
+    }
+}
+
+/// Update the state of a single alert given the latest value of its metric, returning a
+/// notification if the alert fired or resolved.
+///
+/// If the value is unknown, the alert is left as it is.
+fn transition(
+    alerts: &mut HashMap<(String, Option<String>), Alert>,
+    alerting: &Alerting,
+    rule: &Rule,
+    latest: Option<&Latest>,
+    value: Option<f64>,
+) -> Option<Notification> {
+    let value = value?;
+    let key = (
+        rule.name.clone(),
+        latest.map(|latest| latest.identity().to_string()),
+    );
+
+    let (status, started_at) = if rule.op.holds(value, rule.threshold) {
+        let alert = alerts.entry(key.clone()).or_insert_with(|| Alert {
+            pending_since: Instant::now(),
+            started_at: SystemTime::now(),
+            firing: false,
+        });
+        if alert.firing || alert.pending_since.elapsed() < rule.hold {
+            return None;
+        }
+        alert.firing = true;
+        (Status::Firing, alert.started_at)
+    } else {
+        let alert = alerts.remove(&key)?;
+        if !alert.firing {
+            return None;
+        }
+        (Status::Resolved, alert.started_at)
+    };
+
+    let subject = latest
+        .map(Latest::name)
+        .unwrap_or_else(|| "umbrella".to_string());
+    let subject = match &alerting.network {
+        Some(network) => format!("{subject} on {network}"),
+        None => subject,
+    };
+    let summary = match status {
+        Status::Firing => format!(
+            "{}: {subject} {} is {value} ({} {})",
+            rule.name,
+            rule.metric.as_str(),
+            rule.op,
+            rule.threshold
+        ),
+        Status::Resolved => format!("{}: {subject} resolved", rule.name),
+    };
+
+    Some(Notification {
+        status,
+        alert: rule.name.clone(),
+        severity: rule.severity.clone(),
+        validator: key.1,
+        name: latest.and_then(|latest| latest.definition().map(|definition| definition.name)),
+        alias: latest.and_then(|latest| latest.alias().map(str::to_string)),
+        network: alerting.network.clone(),
+        metric: rule.metric,
+        op: rule.op.to_string(),
+        threshold: rule.threshold,
+        value,
+        started_at: humantime::format_rfc3339_seconds(started_at).to_string(),
+        summary,
+    })
+}
+
+/// Send a notification to a single webhook, logging any failure.
+async fn notify(http: reqwest::Client, webhook: Url, notification: Notification) {
+    let result = async {
+        http.post(webhook.clone())
+            .json(&notification)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok::<_, reqwest::Error>(())
+    };
+    if let Err(error) = result.await {
+        error!(%webhook, %error, "failed to notify webhook");
+    }
+}
@@ -0,0 +1,288 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use penumbra_stake::{validator, IdentityKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{incident::Incident, network::Networks, App, Latest};
+
+/// The current state of a validator, as returned by the JSON API.
+#[derive(Serialize, Debug, Clone)]
+pub struct ValidatorView {
+    /// The identity key of the validator.
+    pub identity: String,
+    /// The operator-supplied alias of the validator, if any.
+    pub alias: Option<String>,
+    /// The network the validator is on, if it is one of several.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    /// The on-chain definition of the validator, if known.
+    pub definition: Option<DefinitionView>,
+    /// The status of the validator, if known.
+    pub status: Option<StatusView>,
+    /// The uptime of the validator, if known.
+    pub uptime: Option<UptimeView>,
+    /// The current rates of the validator, if known.
+    pub rates: Option<RatesView>,
+    /// The node the info was last updated from, if any.
+    pub source: Option<String>,
+    /// Whether nodes disagreed about the validator in the most recent update.
+    pub node_disagreement: bool,
+    /// Whether the info was refreshed in the most recent update.
+    pub fresh: bool,
+}
+
+/// The on-chain definition of a validator.
+#[derive(Serialize, Debug, Clone)]
+pub struct DefinitionView {
+    pub name: String,
+    pub website: String,
+    pub description: String,
+    /// The validator's total commission in basis points.
+    pub commission_bps: u32,
+    /// The validator's funding streams.
+    pub funding_streams: Vec<FundingStreamView>,
+}
+
+/// A single funding stream of a validator.
+#[derive(Serialize, Debug, Clone)]
+pub struct FundingStreamView {
+    /// The recipient of the funding stream: an address, or `community-pool`.
+    pub recipient: String,
+    /// The commission taken by the funding stream, in basis points.
+    pub rate_bps: u16,
+}
+
+/// The current rates of a validator.
+#[derive(Serialize, Debug, Clone)]
+pub struct RatesView {
+    /// The number of staking tokens one delegation token is worth.
+    pub exchange_rate: f64,
+    /// The reward rate of the delegation pool for the current epoch.
+    pub reward_rate: f64,
+}
+
+/// The status of a validator.
+#[derive(Serialize, Debug, Clone)]
+pub struct StatusView {
+    /// The state of the validator, like `Active` or `Jailed`.
+    pub state: String,
+    /// The bonding state of the validator: `Bonded`, `Unbonding` or `Unbonded`.
+    pub bonding_state: String,
+    /// The height at which the validator's stake finishes unbonding, if it is unbonding.
+    pub unbonds_at_height: Option<u64>,
+    /// The voting power of the validator.
+    pub voting_power: u64,
+}
+
+/// The uptime of a validator over the on-chain uptime window.
+#[derive(Serialize, Debug, Clone)]
+pub struct UptimeView {
+    /// The height as of which the uptime was reported.
+    pub as_of_height: u64,
+    /// The number of blocks in the window considered for on-chain uptime calculation.
+    pub window: usize,
+    /// The uptime as a percentage of the window.
+    pub percent: f64,
+    /// The number of most-recent consecutive blocks missed.
+    pub consecutive_missed_blocks: usize,
+    /// The heights of the blocks missed within the window, in ascending order.
+    pub missed_blocks: Vec<u64>,
+}
+
+/// A downtime incident of a validator: a run of consecutive missed blocks.
+#[derive(Serialize, Debug, Clone)]
+pub struct IncidentView {
+    /// The first block missed.
+    pub start_height: u64,
+    /// The last block missed so far.
+    pub end_height: u64,
+    /// The number of blocks missed so far.
+    pub blocks_missed: u64,
+    /// Whether the validator is still missing blocks.
+    pub ongoing: bool,
+    /// When the incident was first observed, in RFC 3339 format.
+    pub detected_at: String,
+    /// When the incident was first observed to have ended, in RFC 3339 format, if it has.
+    pub resolved_at: Option<String>,
+    /// The estimated duration of the incident in seconds, as the number of blocks missed times the
+    /// average block time observed, if it is known.
+    pub duration_seconds: Option<f64>,
+}
+
+impl IncidentView {
+    /// Make a view of an incident, given the average block time observed on the chain, if known.
+    fn new(incident: Incident, block_time: Option<f64>) -> Self {
+        IncidentView {
+            start_height: incident.start_height,
+            end_height: incident.end_height,
+            blocks_missed: incident.blocks_missed(),
+            ongoing: incident.ongoing,
+            detected_at: humantime::format_rfc3339_seconds(incident.detected_at).to_string(),
+            resolved_at: incident
+                .resolved_at
+                .map(|resolved_at| humantime::format_rfc3339_seconds(resolved_at).to_string()),
+            duration_seconds: block_time
+                .map(|block_time| incident.blocks_missed() as f64 * block_time),
+        }
+    }
+}
+
+impl From<&Latest> for ValidatorView {
+    fn from(latest: &Latest) -> Self {
+        let status = latest
+            .state()
+            .zip(latest.bonding_state())
+            .zip(latest.voting_power())
+            .map(|((state, bonding_state), voting_power)| {
+                let (bonding_state, unbonds_at_height) = match bonding_state {
+                    validator::BondingState::Bonded => ("Bonded", None),
+                    validator::BondingState::Unbonding { unbonds_at_height } => {
+                        ("Unbonding", Some(unbonds_at_height))
+                    }
+                    validator::BondingState::Unbonded => ("Unbonded", None),
+                };
+                StatusView {
+                    state: state.to_string(),
+                    bonding_state: bonding_state.to_string(),
+                    unbonds_at_height,
+                    voting_power,
+                }
+            });
+
+        let uptime = latest.uptime().map(|uptime| UptimeView {
+            as_of_height: uptime.as_of_height(),
+            window: uptime.missed_blocks_window(),
+            percent: latest.uptime_percent().unwrap_or_default(),
+            consecutive_missed_blocks: latest.consecutive_missed_blocks().unwrap_or_default(),
+            missed_blocks: uptime.missed_blocks().collect(),
+        });
+
+        let rates =
+            latest
+                .exchange_rate()
+                .zip(latest.reward_rate())
+                .map(|(exchange_rate, reward_rate)| RatesView {
+                    exchange_rate,
+                    reward_rate,
+                });
+
+        ValidatorView {
+            identity: latest.identity().to_string(),
+            alias: latest.alias().map(str::to_string),
+            network: None,
+            definition: latest.definition().map(|definition| DefinitionView {
+                name: definition.name,
+                website: definition.website,
+                description: definition.description,
+                commission_bps: latest.commission_bps().unwrap_or_default(),
+                funding_streams: latest
+                    .funding_streams()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(recipient, rate_bps)| FundingStreamView {
+                        recipient,
+                        rate_bps,
+                    })
+                    .collect(),
+            }),
+            source: latest.source().map(|source| source.to_string()),
+            node_disagreement: latest.disagreement(),
+            fresh: latest.is_fresh(),
+            status,
+            uptime,
+            rates,
+        }
+    }
+}
+
+/// Which network to look for validators on, if there are several.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct NetworkQuery {
+    pub network: Option<String>,
+}
+
+impl NetworkQuery {
+    /// Get the applications monitoring the networks which match the query.
+    fn select<'a>(&'a self, networks: &'a Networks) -> impl Iterator<Item = &'a App> {
+        networks.apps().iter().filter(|app| {
+            self.network
+                .as_deref()
+                .map_or(true, |network| app.network() == Some(network))
+        })
+    }
+}
+
+/// Make the view of a validator on a network.
+fn view(app: &App, latest: &Latest) -> ValidatorView {
+    ValidatorView {
+        network: app.network().map(str::to_string),
+        ..latest.into()
+    }
+}
+
+/// List the current state of every monitored validator, on every network or only the one given.
+pub async fn validators_handler(
+    State(networks): State<Networks>,
+    Query(query): Query<NetworkQuery>,
+) -> Json<Vec<ValidatorView>> {
+    Json(
+        query
+            .select(&networks)
+            .flat_map(|app| {
+                app.info()
+                    .iter()
+                    .map(|latest| view(app, latest))
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+    )
+}
+
+/// Get the current state of a single monitored validator.
+pub async fn validator_handler(
+    State(networks): State<Networks>,
+    Path(identity): Path<String>,
+    Query(query): Query<NetworkQuery>,
+) -> Result<Json<ValidatorView>, StatusCode> {
+    let (app, latest) = find(&networks, &query, &identity)?;
+    Ok(Json(view(app, &latest)))
+}
+
+/// List the most recent downtime incidents of a single monitored validator, oldest first.
+pub async fn incidents_handler(
+    State(networks): State<Networks>,
+    Path(identity): Path<String>,
+    Query(query): Query<NetworkQuery>,
+) -> Result<Json<Vec<IncidentView>>, StatusCode> {
+    let (app, latest) = find(&networks, &query, &identity)?;
+    let block_time = app.chain().average_block_time();
+    Ok(Json(
+        latest
+            .incidents()
+            .into_iter()
+            .map(|incident| IncidentView::new(incident, block_time))
+            .collect(),
+    ))
+}
+
+/// Find a monitored validator by its identity key, on the first matching network it is monitored
+/// on, failing with `400` if the key is invalid or `404` if the validator isn't monitored.
+fn find<'a>(
+    networks: &'a Networks,
+    query: &'a NetworkQuery,
+    identity: &str,
+) -> Result<(&'a App, Latest), StatusCode> {
+    let identity: IdentityKey = identity.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    query
+        .select(networks)
+        .find_map(|app| {
+            app.info()
+                .into_iter()
+                .find(|latest| latest.identity() == identity)
+                .map(|latest| (app, latest))
+        })
+        .ok_or(StatusCode::NOT_FOUND)
+}
@@ -1,81 +1,601 @@
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
+use penumbra_stake::{validator, IdentityKey};
+use prometheus::proto::MetricFamily;
 use std::{
+    collections::{BTreeMap, HashSet},
+    convert::Infallible,
+    path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
     time::Duration,
 };
-use tokio::time::Instant;
+use tokio::time::{sleep_until, Instant};
 
-use crate::{report, update, Client, Latest};
+use crate::{
+    alert::{Alerter, Alerting},
+    chain::{self, Progress},
+    discover::{total_voting_power, validator_set, Discovery},
+    latest::Spec,
+    penalty::{update_penalties, Epoch},
+    persist::{self, LastSaved},
+    report::{self, Metrics},
+    update, Client, Latest, Options,
+};
+
+/// How often to read the validator set just to measure the total voting power of the active set,
+/// which changes slowly, when it isn't read on every update to discover validators.
+const VOTING_POWER_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// When the application updates its information from the fullnodes.
+#[derive(clap::ValueEnum, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Update only when metrics are requested, caching the result for the polling interval.
+    #[default]
+    OnDemand,
+    /// Update continuously in the background at the polling interval, and serve the cached result
+    /// when metrics are requested.
+    Background,
+}
 
 /// The main application state.
 #[derive(Debug, Clone)]
 pub struct App {
+    /// The nodes and validators to update, and how to update them, which can be reconfigured while
+    /// the application is running.
+    targets: Arc<RwLock<Targets>>,
+    /// The time of the last update.
+    last_update: Arc<Mutex<Option<Instant>>>,
+    /// The last update success.
+    last_success: Arc<AtomicBool>,
+    /// Whether any update has ever been successful.
+    ever_succeeded: Arc<AtomicBool>,
+    /// The total voting power of the active validator set, as of the last time the validator set
+    /// was read.
+    total_voting_power: Arc<Mutex<Option<u64>>>,
+    /// The last time the validator set was read.
+    last_validator_set: Arc<Mutex<Option<Instant>>>,
+    /// The state file as it was last saved, if it ever was.
+    last_saved: Arc<Mutex<Option<LastSaved>>>,
+    /// The epoch of the chain as last reported by a node, if any node ever reported it.
+    last_epoch: Arc<Mutex<Option<Epoch>>>,
+    /// The observed progress of the chain.
+    chain: Arc<Mutex<Progress>>,
+    /// Whether updates happen on-demand or in the background.
+    mode: Mode,
+    /// The state of the alerting engine.
+    alerter: Alerter,
+    /// The name of the network monitored, if it is one of several.
+    network: Option<String>,
+    /// The registry into which the metrics for the network are recorded.
+    metrics: Metrics,
+}
+
+/// The reconfigurable part of the application state.
+#[derive(Debug, Clone)]
+pub struct Targets {
     /// The sets of nodes to use to update the info for each validator.
     ///
     /// Each set of nodes is tried in order, with all the nodes in each set tried concurrently. Once
     /// all validators have been updated, no more nodes are tried.
-    node_sets: Vec<Vec<Client>>,
+    pub node_sets: Vec<Vec<Client>>,
     /// The latest info for each validator.
-    info: Vec<Latest>,
-    /// The time of the last update.
-    last_update: Arc<Mutex<Option<Instant>>>,
-    /// The last update success.
-    last_success: Arc<AtomicBool>,
+    pub info: Vec<Latest>,
+    /// The validators specified explicitly, which are monitored regardless of discovery.
+    pub explicit: Vec<IdentityKey>,
+    /// Which validators to discover automatically and add to (or remove from) `info`.
+    pub discovery: Discovery,
+    /// The alerting rules and webhooks.
+    pub alerting: Alerting,
     /// The minimum polling interval.
-    poll_interval: Duration,
+    pub poll_interval: Duration,
     /// The timeout for connecting to each fullnode.
-    connect_timeout: Duration,
+    pub connect_timeout: Duration,
+    /// The maximum age of each validator's info for the application to be considered ready.
+    pub max_staleness: Duration,
+    /// How long the chain's height must stop advancing before the chain is considered halted.
+    pub halt_threshold: Duration,
+    /// The file in which to save the latest info about each validator, if any.
+    pub state_file: Option<PathBuf>,
+    /// The prefix of the name of every metric.
+    pub namespace: String,
+    /// The constant labels attached to every metric, by name.
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Targets {
+    /// Discover validators from the validator set, if enabled, adding and removing info cells so
+    /// that exactly the discovered and explicitly specified validators are monitored.
+    pub fn discover(&mut self, validator_set: &[validator::Info]) {
+        let Some(discovered) = self.discovery.select(validator_set) else {
+            return;
+        };
+
+        let Self { info, explicit, .. } = self;
+        info.retain(|latest| {
+            let validator = latest.identity();
+            let keep = discovered.contains(&validator) || explicit.contains(&validator);
+            if !keep {
+                info!(%validator, "validator no longer discovered, no longer monitoring");
+            }
+            keep
+        });
+        for validator in discovered {
+            if !info.iter().any(|latest| latest.identity() == validator) {
+                info!(%validator, "discovered validator, now monitoring");
+                info.push(Latest::new(Spec {
+                    identity: validator,
+                    alias: None,
+                }));
+            }
+        }
+    }
+
+    /// Get the validator set from the primary nodes, including inactive validators only if they are
+    /// all to be discovered.
+    ///
+    /// Returns `None` if there are no primary nodes or none of them could answer.
+    pub async fn validator_set(&self) -> Option<Vec<validator::Info>> {
+        let primaries = self.node_sets.first().filter(|nodes| !nodes.is_empty())?;
+        validator_set(
+            primaries,
+            self.discovery == Discovery::All,
+            self.connect_timeout,
+        )
+        .await
+    }
+
+    /// Get the validator set and discover validators from it, if discovery is enabled, returning
+    /// the validator set if it was read.
+    pub async fn rediscover(&mut self) -> Option<Vec<validator::Info>> {
+        if self.discovery == Discovery::Off {
+            return None;
+        }
+        let validator_set = self.validator_set().await?;
+        self.discover(&validator_set);
+        Some(validator_set)
+    }
+
+    /// Carry over the clients and info cells from a previous set of targets wherever they refer to
+    /// the same node (with the same TLS settings) or validator, so that connections and cached info
+    /// survive a reconfiguration.
+    fn inherit(mut self, previous: &Targets) -> Self {
+        for client in self.node_sets.iter_mut().flatten() {
+            if let Some(existing) =
+                previous.node_sets.iter().flatten().find(|existing| {
+                    existing.uri() == client.uri() && existing.tls() == client.tls()
+                })
+            {
+                *client = existing.clone();
+            }
+        }
+        for latest in self.info.iter_mut() {
+            if let Some(existing) = previous
+                .info
+                .iter()
+                .find(|existing| existing.identity() == latest.identity())
+            {
+                let alias = latest.alias().map(str::to_string);
+                *latest = existing.clone();
+                latest.set_alias(alias);
+            }
+        }
+        // Keep previously discovered validators until the next discovery decides otherwise:
+        if self.discovery != Discovery::Off {
+            for existing in previous.info.iter() {
+                if !self
+                    .info
+                    .iter()
+                    .any(|latest| latest.identity() == existing.identity())
+                {
+                    self.info.push(existing.clone());
+                }
+            }
+        }
+        self
+    }
 }
 
 impl App {
-    /// Make a new application with the given sets of nodes and latest info.
-    pub fn new(
-        node_sets: Vec<Vec<Client>>,
-        info: Vec<Latest>,
-        poll_interval: Duration,
-        connect_timeout: Duration,
-    ) -> Self {
+    /// Make a new application with the given update mode and targets, monitoring the given network
+    /// if it is one of several.
+    pub fn new(mode: Mode, network: Option<String>, targets: Targets) -> Self {
         Self {
-            node_sets,
-            info,
+            targets: Arc::new(RwLock::new(targets)),
             last_update: Arc::new(Mutex::new(None)),
             last_success: Arc::new(AtomicBool::new(true)),
-            poll_interval,
-            connect_timeout,
+            ever_succeeded: Arc::new(AtomicBool::new(false)),
+            total_voting_power: Arc::new(Mutex::new(None)),
+            last_validator_set: Arc::new(Mutex::new(None)),
+            last_saved: Arc::new(Mutex::new(None)),
+            last_epoch: Arc::new(Mutex::new(None)),
+            chain: Arc::new(Mutex::new(Progress::default())),
+            mode,
+            alerter: Alerter::default(),
+            network,
+            metrics: Metrics::new(),
         }
     }
 
-    /// Run the application forever, updating the info for each validator at the polling interval.
+    /// Replace the nodes and validators of the running application with those in the given
+    /// options, keeping any existing connections and info for nodes and validators which remain.
+    pub fn reconfigure(&self, options: Options) {
+        if options.mode != self.mode {
+            warn!(
+                current = ?self.mode,
+                requested = ?options.mode,
+                "changing the update mode requires a restart"
+            );
+        }
+
+        let mut targets = self.targets.write();
+        let reconfigured = options.into_targets().inherit(&targets);
+        *targets = reconfigured;
+        info!(
+            validators = targets.info.len(),
+            node_sets = targets.node_sets.len(),
+            "reconfigured"
+        );
+    }
+
+    /// Get a snapshot of the current targets.
+    fn targets(&self) -> Targets {
+        self.targets.read().clone()
+    }
+
+    /// Get the latest info for all the validators currently being monitored.
+    pub fn info(&self) -> Vec<Latest> {
+        self.targets.read().info.clone()
+    }
+
+    /// Get the identity keys of all the validators currently being monitored.
+    pub fn validators(&self) -> Vec<IdentityKey> {
+        self.targets
+            .read()
+            .info
+            .iter()
+            .map(Latest::identity)
+            .collect()
+    }
+
+    /// Get the URIs of all the nodes currently configured, in every node set.
+    pub fn nodes(&self) -> Vec<String> {
+        self.targets
+            .read()
+            .node_sets
+            .iter()
+            .flatten()
+            .map(|client| client.uri().to_string())
+            .collect()
+    }
+
+    /// Get the observed progress of the chain.
+    pub fn chain(&self) -> Progress {
+        self.chain.lock().clone()
+    }
+
+    /// Get the prefix of the name of every metric.
+    pub fn namespace(&self) -> String {
+        self.targets.read().namespace.clone()
+    }
+
+    /// Get the name of the network monitored, if it is one of several.
+    pub fn network(&self) -> Option<&str> {
+        self.network.as_deref()
+    }
+
+    /// Get the update mode of the application.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Prepare the metrics for a scrape.
+    ///
+    /// In on-demand mode, this updates the info for each validator if the polling interval has
+    /// elapsed; in background mode, this only reports the info cached by [`App::poll`].
+    pub async fn scrape(&self) {
+        match self.mode {
+            Mode::OnDemand => self.update().await,
+            Mode::Background => self.report(),
+        }
+    }
+
+    /// Update the info for each validator, if it has not been updated within the polling interval,
+    /// then report metrics.
     pub async fn update(&self) {
         // The locking here prevents multiple updates from happening concurrently within the same
         // polling interval, by atomically bumping the last update time to the current time.
-        let (needs_update, now) = {
+        let needs_update = {
             // Check if an update is required
             let mut last_update = self.last_update.lock();
             let needs_update = match *last_update {
                 None => true,
-                Some(last_update) => last_update.elapsed() >= self.poll_interval,
+                Some(last_update) => last_update.elapsed() >= self.targets.read().poll_interval,
             };
 
             if needs_update {
                 *last_update = Some(Instant::now());
             }
 
-            (needs_update, *last_update)
+            needs_update
         };
 
         if needs_update {
-            self.last_success.store(
-                update(&self.node_sets, &self.info, self.connect_timeout).await,
-                Ordering::SeqCst,
-            );
+            self.refresh().await;
         }
 
         // Emit metrics unconditionally, even if the update was not performed (this ensures that the
         // staleness metric is updated)
-        report(self.last_success.load(Ordering::SeqCst), now, &self.info);
+        self.report();
+    }
+
+    /// Run forever, updating the info for each validator at the polling interval and reporting
+    /// metrics after each update.
+    pub async fn poll(self) -> Infallible {
+        loop {
+            let start = Instant::now();
+            *self.last_update.lock() = Some(start);
+            self.refresh().await;
+            self.report();
+            // If an update takes longer than the polling interval, start the next one as soon as it
+            // finishes, rather than trying to catch up with a burst of updates:
+            let next = start + self.targets.read().poll_interval;
+            sleep_until(next).await;
+        }
     }
+
+    /// Check whether the application is ready to serve meaningful data, returning the reasons it is
+    /// not if it isn't.
+    ///
+    /// The application is ready if at least one update has succeeded, the info for every validator
+    /// was updated within the maximum staleness, and at least one node is connected. This does not
+    /// query any nodes.
+    pub fn readiness(&self) -> Result<(), Vec<String>> {
+        let targets = self.targets();
+        let mut reasons = Vec::new();
+
+        if !self.ever_succeeded.load(Ordering::SeqCst) {
+            reasons.push("no update has succeeded yet".to_string());
+        }
+
+        for latest in targets.info.iter() {
+            match latest.age() {
+                None => reasons.push(format!("no info for validator {}", latest.identity())),
+                Some(age) if age > targets.max_staleness => reasons.push(format!(
+                    "info for validator {} is stale ({} old)",
+                    latest.identity(),
+                    humantime::format_duration(Duration::from_secs(age.as_secs())),
+                )),
+                Some(_) => {}
+            }
+        }
+
+        if !targets.node_sets.iter().flatten().any(Client::is_connected) {
+            reasons.push("no node is connected".to_string());
+        }
+
+        if reasons.is_empty() {
+            Ok(())
+        } else {
+            Err(reasons)
+        }
+    }
+
+    /// Gather all metrics from the registry, omitting any series for validators which are no
+    /// longer monitored (because they were removed from the configuration or are no longer
+    /// discovered on the chain) or for nodes which are no longer configured, and applying the
+    /// namespace and constant labels.
+    ///
+    /// The `chain_id` label is attached automatically once a node has reported the chain ID, unless
+    /// it is set explicitly, and the `network` label is attached if the network is one of several.
+    pub fn gather(&self) -> Vec<MetricFamily> {
+        let validators = self
+            .validators()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<HashSet<_>>();
+        let nodes = self.nodes().into_iter().collect::<HashSet<_>>();
+        // A validator's `validator_info` series is labeled with its names, so whenever they change
+        // a new series is made, and only the one with its current names is kept:
+        let info_labels = self
+            .info()
+            .iter()
+            .map(|latest| sorted_labels(report::info_labels(latest)))
+            .collect::<HashSet<_>>();
+        // Likewise, only the series for recipients of a validator's current funding streams are
+        // kept:
+        let recipients = self
+            .info()
+            .iter()
+            .flat_map(|latest| {
+                let validator = latest.identity().to_string();
+                latest
+                    .funding_streams()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(move |(recipient, _)| (validator.clone(), recipient))
+            })
+            .collect::<HashSet<_>>();
+
+        let mut families = self.metrics.gather();
+        for family in families.iter_mut() {
+            family.mut_metric().retain(|metric| {
+                metric
+                    .get_label()
+                    .iter()
+                    .all(|label| match label.get_name() {
+                        "validator" => validators.contains(label.get_value()),
+                        "node" => nodes.contains(label.get_value()),
+                        _ => true,
+                    })
+            });
+            if family.get_name() == "validator_info" {
+                family.mut_metric().retain(|metric| {
+                    let labels = metric
+                        .get_label()
+                        .iter()
+                        .map(|label| (label.get_name(), label.get_value().to_string()));
+                    info_labels.contains(&sorted_labels(labels))
+                });
+            }
+            if family.get_name() == "funding_stream_rate_bps" {
+                family.mut_metric().retain(|metric| {
+                    let label = |name: &str| {
+                        metric
+                            .get_label()
+                            .iter()
+                            .find(|label| label.get_name() == name)
+                            .map(|label| label.get_value().to_string())
+                            .unwrap_or_default()
+                    };
+                    recipients.contains(&(label("validator"), label("recipient")))
+                });
+            }
+        }
+        families.retain(|family| !family.get_metric().is_empty());
+
+        let (namespace, mut labels) = {
+            let targets = self.targets.read();
+            (targets.namespace.clone(), targets.labels.clone())
+        };
+        if let Some(chain_id) = self.chain.lock().chain_id.clone() {
+            labels.entry("chain_id".to_string()).or_insert(chain_id);
+        }
+        if let Some(network) = &self.network {
+            labels.insert("network".to_string(), network.clone());
+        }
+        report::decorate(&mut families, &namespace, &labels);
+        families
+    }
+
+    /// Unconditionally update the info for each validator from the fullnodes.
+    async fn refresh(&self) {
+        // Observe the progress of the chain itself, to tell a halted chain apart from a validator
+        // which is down:
+        let targets = self.targets();
+        let status = chain::status(&targets.node_sets, targets.connect_timeout).await;
+        self.chain.lock().observe(status);
+
+        // Get the validator set (including inactive validators only if they are to be discovered),
+        // to discover validators and measure the total voting power of the active set. It's a large
+        // response, so it's never asked of fallbacks, and only read on every update if validators
+        // are to be discovered:
+        let due = targets.discovery != Discovery::Off
+            || (!targets.info.is_empty()
+                && self
+                    .last_validator_set
+                    .lock()
+                    .map_or(true, |last| last.elapsed() >= VOTING_POWER_INTERVAL));
+        if due {
+            // The targets may be reconfigured while the validator set is read, so validators are
+            // discovered on the current targets rather than on this copy:
+            if let Some(validator_set) = targets.validator_set().await {
+                *self.last_validator_set.lock() = Some(Instant::now());
+                self.targets.write().discover(&validator_set);
+                *self.total_voting_power.lock() = Some(total_voting_power(&validator_set));
+            }
+        }
+
+        let targets = self.targets();
+        let success = update(&targets.node_sets, &targets.info, targets.connect_timeout).await;
+
+        // Check the slashing penalties as of the most recent height any validator was updated at:
+        if let Some(height) = targets.info.iter().filter_map(Latest::as_of_height).max() {
+            update_penalties(
+                &targets.node_sets,
+                &targets.info,
+                height,
+                targets.connect_timeout,
+                &self.last_epoch,
+            )
+            .await;
+        }
+
+        self.last_success.store(success, Ordering::SeqCst);
+        self.ever_succeeded.fetch_or(success, Ordering::SeqCst);
+        let chain_halted = self.chain.lock().is_halted(targets.halt_threshold);
+        self.alerter
+            .evaluate(&targets.alerting, success, chain_halted, &targets.info);
+
+        if let Some(path) = &targets.state_file {
+            if let Err(error) = persist::save(path, &targets.info, &self.last_saved).await {
+                error!(path = %path.display(), %error, "failed to save state");
+            }
+        }
+    }
+
+    /// Restore the info for each validator from the state file, if there is one.
+    ///
+    /// Validators which are no longer monitored are ignored, unless validators are discovered, in
+    /// which case they are monitored until the next discovery decides otherwise.
+    pub async fn restore(&self) {
+        let Some(path) = self.targets().state_file else {
+            return;
+        };
+        let saved = match persist::load(&path).await {
+            Ok(saved) => saved,
+            Err(error) => {
+                error!(path = %path.display(), %error, "failed to load state");
+                return;
+            }
+        };
+
+        let mut targets = self.targets.write();
+        let discovery = targets.discovery;
+        for saved in saved {
+            let latest = match targets
+                .info
+                .iter()
+                .find(|latest| latest.identity() == saved.identity)
+            {
+                Some(latest) => latest.clone(),
+                None if discovery != Discovery::Off => {
+                    let latest = Latest::new(Spec {
+                        identity: saved.identity,
+                        alias: None,
+                    });
+                    targets.info.push(latest.clone());
+                    latest
+                }
+                None => continue,
+            };
+            let validator = latest.identity();
+            match latest.restore(saved) {
+                Ok(()) => info!(%validator, "restored saved state"),
+                Err(error) => warn!(%validator, %error, "failed to restore saved state"),
+            }
+        }
+    }
+
+    /// Emit metrics for the current state of the application.
+    fn report(&self) {
+        let targets = self.targets();
+        let chain = self.chain.lock().clone();
+        let last_update = *self.last_update.lock();
+        let total_voting_power = *self.total_voting_power.lock();
+        self.metrics.record(|| {
+            report::report(
+                self.last_success.load(Ordering::SeqCst),
+                last_update,
+                total_voting_power,
+                &chain,
+                targets.halt_threshold,
+                &targets.node_sets,
+                &targets.info,
+            )
+        });
+    }
+}
+
+/// Collect the name-value pairs of a set of labels, sorted by name so that they can be compared
+/// regardless of order.
+fn sorted_labels<'a>(labels: impl IntoIterator<Item = (&'a str, String)>) -> Vec<(String, String)> {
+    let mut labels = labels
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect::<Vec<_>>();
+    labels.sort();
+    labels
 }
@@ -0,0 +1,123 @@
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        HeaderValue, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::{fmt, str::FromStr};
+
+/// Credentials for HTTP basic authentication, given as `USER:PASSWORD`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    user: String,
+    password: String,
+}
+
+impl FromStr for Credentials {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (user, password) = s
+            .split_once(':')
+            .ok_or_else(|| eyre!("expected USER:PASSWORD"))?;
+        ensure!(!user.is_empty(), "empty user name");
+        Ok(Self {
+            user: user.to_string(),
+            password: password.to_string(),
+        })
+    }
+}
+
+impl Credentials {
+    /// Get the user name.
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// Get the password.
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+}
+
+// Never print the password, even in debug output:
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("user", &self.user)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The credentials required to access the server: if both a bearer token and basic credentials are
+/// set, either is accepted, and if neither is set, access is unrestricted.
+#[derive(Debug, Clone, Default)]
+pub struct Auth {
+    bearer_token: Option<String>,
+    basic: Option<Credentials>,
+}
+
+impl Auth {
+    /// Require either of the given credentials, if any.
+    pub fn new(bearer_token: Option<String>, basic: Option<Credentials>) -> Self {
+        Self {
+            bearer_token,
+            basic,
+        }
+    }
+
+    /// Check whether a request with the given `Authorization` header (if any) may be served.
+    pub fn allows(&self, authorization: Option<&str>) -> bool {
+        if self.bearer_token.is_none() && self.basic.is_none() {
+            return true;
+        }
+        let Some((scheme, value)) = authorization.and_then(|header| header.split_once(' ')) else {
+            return false;
+        };
+        let value = value.trim();
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            if let Some(token) = &self.bearer_token {
+                return constant_time_eq(token.as_bytes(), value.as_bytes());
+            }
+        } else if scheme.eq_ignore_ascii_case("basic") {
+            if let (Some(credentials), Ok(decoded)) = (&self.basic, STANDARD.decode(value)) {
+                let expected = format!("{}:{}", credentials.user, credentials.password);
+                return constant_time_eq(expected.as_bytes(), &decoded);
+            }
+        }
+        false
+    }
+}
+
+/// Compare two secrets in time depending only on their lengths, so that the comparison doesn't
+/// leak how much of a guess was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Middleware rejecting requests without the required credentials with `401 Unauthorized`.
+pub async fn require(State(auth): State<Auth>, request: Request, next: Next) -> Response {
+    let authorization = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok());
+    if auth.allows(authorization) {
+        return next.run(request).await;
+    }
+
+    let challenge = if auth.basic.is_some() {
+        r#"Basic realm="umbrella""#
+    } else {
+        r#"Bearer realm="umbrella""#
+    };
+    let mut response = StatusCode::UNAUTHORIZED.into_response();
+    response
+        .headers_mut()
+        .insert(WWW_AUTHENTICATE, HeaderValue::from_static(challenge));
+    response
+}
@@ -0,0 +1,163 @@
+use penumbra_proto::util::tendermint_proxy::v1::GetStatusRequest;
+use std::{collections::VecDeque, time::Duration};
+use tokio::{
+    task::JoinSet,
+    time::{timeout, Instant},
+};
+
+use crate::Client;
+
+/// The number of most recent observations of the chain's height to average block time over.
+const BLOCK_TIME_SAMPLES: usize = 32;
+
+/// The status of the chain as reported by a node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    /// The chain ID of the network the node is on.
+    pub chain_id: String,
+    /// The height of the latest block the node knows of.
+    pub height: u64,
+    /// The time of the latest block the node knows of, in seconds since the Unix epoch.
+    pub block_time: f64,
+}
+
+/// The observed progress of the chain itself, independent of any validator.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    /// The chain ID of the network, if any node has ever reported it.
+    pub chain_id: Option<String>,
+    /// The highest block height any node has ever reported.
+    pub height: Option<u64>,
+    /// When the height was last seen to advance.
+    pub advanced_at: Option<Instant>,
+    /// Whether any node reported the status of the chain in the most recent update.
+    pub reachable: bool,
+    /// Recent observations of the height and block time, oldest first, for averaging block time.
+    samples: VecDeque<(u64, f64)>,
+}
+
+impl Progress {
+    /// Record the status of the chain as reported in an update, or `None` if no node reported it.
+    ///
+    /// The height only ever moves forward, even if a node reports an older height.
+    pub fn observe(&mut self, status: Option<Status>) {
+        let Some(status) = status else {
+            self.reachable = false;
+            return;
+        };
+        self.reachable = true;
+        self.chain_id = Some(status.chain_id);
+
+        if self.height.map_or(true, |height| status.height > height) {
+            self.height = Some(status.height);
+            self.advanced_at = Some(Instant::now());
+            self.samples.push_back((status.height, status.block_time));
+            if self.samples.len() > BLOCK_TIME_SAMPLES {
+                self.samples.pop_front();
+            }
+        }
+    }
+
+    /// Get the average time between blocks over the recent observations, if at least two distinct
+    /// heights have been observed.
+    pub fn average_block_time(&self) -> Option<f64> {
+        let (&(first_height, first_time), &(last_height, last_time)) =
+            (self.samples.front()?, self.samples.back()?);
+        if last_height <= first_height {
+            return None;
+        }
+        Some((last_time - first_time) / (last_height - first_height) as f64)
+    }
+
+    /// Get the time elapsed since the height was last seen to advance, if it ever was.
+    pub fn stalled_for(&self) -> Option<Duration> {
+        self.advanced_at.map(|advanced_at| advanced_at.elapsed())
+    }
+
+    /// Check whether the chain has halted: whether the height has not advanced across any node for
+    /// longer than the given threshold, even though nodes are reachable.
+    ///
+    /// Returns `None` if no node is reachable, since then a halted chain can't be told apart from
+    /// unreachable nodes.
+    pub fn is_halted(&self, threshold: Duration) -> Option<bool> {
+        if !self.reachable {
+            return None;
+        }
+        Some(self.stalled_for()? > threshold)
+    }
+}
+
+/// Get the status of the chain from the nodes, taking the highest height reported by any node in
+/// the first set of nodes in which any node answers.
+///
+/// Returns `None` if no node could answer.
+pub async fn status(node_sets: &[Vec<Client>], connect_timeout: Duration) -> Option<Status> {
+    for nodes in node_sets.iter() {
+        let mut tasks = JoinSet::new();
+        for client in nodes.iter().cloned() {
+            tasks.spawn(async move {
+                if let Err(error) = client.connect(connect_timeout).await {
+                    warn!(node = %client.uri(), "{}", error);
+                    return None;
+                }
+                // Not every node proxies its CometBFT RPC, so failing to get the status of the
+                // chain doesn't count against the node's health or connection:
+                match timeout(connect_timeout, status_from(&client)).await {
+                    Ok(Ok(status)) => Some(status),
+                    Ok(Err(error)) => {
+                        warn!(node = %client.uri(), "failed to get chain status: {}", error);
+                        None
+                    }
+                    Err(elapsed) => {
+                        warn!(node = %client.uri(), "failed to get chain status: {}", elapsed);
+                        None
+                    }
+                }
+            });
+        }
+
+        let mut latest: Option<Status> = None;
+        while let Some(result) = tasks.join_next().await {
+            if let Ok(Some(status)) = result {
+                if latest
+                    .as_ref()
+                    .map_or(true, |latest| status.height > latest.height)
+                {
+                    latest = Some(status);
+                }
+            }
+        }
+        if latest.is_some() {
+            return latest;
+        }
+    }
+
+    error!("failed to get chain status from any data source");
+    None
+}
+
+/// Get the status of the chain from a single node.
+async fn status_from(client: &Client) -> eyre::Result<Status> {
+    let mut tendermint_client = client
+        .tendermint_proxy()
+        .ok_or_else(|| eyre!("client is not connected"))?;
+
+    let response = tendermint_client
+        .get_status(GetStatusRequest {})
+        .await?
+        .into_inner();
+    let chain_id = response
+        .node_info
+        .ok_or_else(|| eyre!("no node info"))?
+        .network;
+    let sync_info = response.sync_info.ok_or_else(|| eyre!("no sync info"))?;
+    let block_time = sync_info
+        .latest_block_time
+        .ok_or_else(|| eyre!("no latest block time"))?;
+
+    Ok(Status {
+        chain_id,
+        height: sync_info.latest_block_height,
+        block_time: block_time.seconds as f64 + f64::from(block_time.nanos) / 1e9,
+    })
+}
@@ -0,0 +1,148 @@
+use penumbra_stake::validator;
+use std::fmt::{self, Display};
+
+use crate::{discover::Discovery, update, Latest, Options};
+
+/// Check the uptime of each validator once, as a Nagios or Icinga plugin, exiting with the status
+/// of the worst validator.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Check {
+    #[clap(flatten)]
+    pub options: Options,
+    /// Uptime percentage below which a validator is in the WARNING state.
+    #[clap(long, default_value_t = 99.0)]
+    pub warn_uptime: f64,
+    /// Uptime percentage below which a validator is in the CRITICAL state.
+    #[clap(long, default_value_t = 95.0)]
+    pub crit_uptime: f64,
+}
+
+/// The outcome of a check, in increasing order of severity.
+///
+/// This is not the order of the exit codes: as when Nagios combines the states of several services,
+/// a CRITICAL validator outranks one nothing is known about, since the check has then found a
+/// definite problem, while UNKNOWN outranks WARNING and OK, since the validator may be worse off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Ok,
+    Warning,
+    Unknown,
+    Critical,
+}
+
+impl Outcome {
+    /// Get the exit code with which a plugin reports the outcome.
+    pub fn code(&self) -> i32 {
+        match self {
+            Outcome::Ok => 0,
+            Outcome::Warning => 1,
+            Outcome::Critical => 2,
+            Outcome::Unknown => 3,
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Ok => "OK",
+            Outcome::Warning => "WARNING",
+            Outcome::Unknown => "UNKNOWN",
+            Outcome::Critical => "CRITICAL",
+        })
+    }
+}
+
+impl Check {
+    /// Update the info for each validator once, and evaluate it, returning the outcome and the line
+    /// of plugin output reporting it.
+    ///
+    /// Anything preventing the check from running, like invalid options, is an UNKNOWN outcome.
+    pub async fn run(self) -> (Outcome, String) {
+        let options = match self.options.clone().load().await {
+            Ok(options) => options,
+            Err(error) => return (Outcome::Unknown, format!("UMBRELLA UNKNOWN - {error}")),
+        };
+        if self.crit_uptime > self.warn_uptime {
+            return (
+                Outcome::Unknown,
+                "UMBRELLA UNKNOWN - `--crit-uptime` is above `--warn-uptime`".to_string(),
+            );
+        }
+        // A single update can't span several networks:
+        if !options.networks.is_empty() {
+            return (
+                Outcome::Unknown,
+                "UMBRELLA UNKNOWN - `networks` in a configuration file aren't supported"
+                    .to_string(),
+            );
+        }
+
+        let mut targets = options.into_targets();
+        if targets.discovery != Discovery::Off && targets.rediscover().await.is_none() {
+            return (
+                Outcome::Unknown,
+                "UMBRELLA UNKNOWN - failed to get the validator set from any primary node"
+                    .to_string(),
+            );
+        }
+        update(&targets.node_sets, &targets.info, targets.connect_timeout).await;
+        self.evaluate(&targets.info)
+    }
+
+    /// Evaluate the latest info for each validator against the thresholds, returning the worst
+    /// outcome and the line of plugin output reporting it, with performance data.
+    ///
+    /// A validator is CRITICAL if it is jailed, tombstoned or disabled, or its uptime is below the
+    /// critical threshold; WARNING if its uptime is below the warning threshold; and UNKNOWN if no
+    /// node reported on it.
+    pub fn evaluate(&self, info: &[Latest]) -> (Outcome, String) {
+        let mut worst = Outcome::Ok;
+        let mut details = Vec::new();
+        let mut perfdata = Vec::new();
+
+        for latest in info {
+            let name = latest.name();
+            let (Some(state), Some(uptime), Some(missed)) = (
+                latest.state(),
+                latest.uptime_percent(),
+                latest.consecutive_missed_blocks(),
+            ) else {
+                worst = worst.max(Outcome::Unknown);
+                details.push(format!("{name}: no data from any node"));
+                continue;
+            };
+
+            let (outcome, reason) = match state {
+                validator::State::Jailed
+                | validator::State::Tombstoned
+                | validator::State::Disabled => (Outcome::Critical, format!(", {state}")),
+                _ if uptime < self.crit_uptime => {
+                    (Outcome::Critical, format!(" (< {})", self.crit_uptime))
+                }
+                _ if uptime < self.warn_uptime => {
+                    (Outcome::Warning, format!(" (< {})", self.warn_uptime))
+                }
+                _ => (Outcome::Ok, String::new()),
+            };
+            worst = worst.max(outcome);
+            details.push(format!("{name}: uptime {uptime:.2}%{reason}"));
+
+            // Perfdata labels are quoted, with quotes inside them doubled; the thresholds are `N:`
+            // ranges, so that only uptime below them is out of range:
+            let label = name.replace('\'', "''");
+            perfdata.push(format!(
+                "'{label} uptime'={uptime:.2}%;{}:;{}:;0;100",
+                self.warn_uptime, self.crit_uptime
+            ));
+            perfdata.push(format!("'{label} consecutive missed blocks'={missed};;;0"));
+        }
+
+        let mut line = format!("UMBRELLA {worst} - {}", details.join("; "));
+        if !perfdata.is_empty() {
+            line.push_str(" | ");
+            line.push_str(&perfdata.join(" "));
+        }
+        (worst, line)
+    }
+}
@@ -1,14 +1,81 @@
 use eyre::Ok;
 use parking_lot::RwLock;
-use penumbra_proto::core::component::stake::v1::query_service_client::QueryServiceClient as StakeQueryServiceClient;
-use std::sync::Arc;
-use tonic::transport::{Channel, Uri};
+use penumbra_proto::{
+    core::component::{
+        sct::v1::query_service_client::QueryServiceClient as SctQueryServiceClient,
+        stake::v1::query_service_client::QueryServiceClient as StakeQueryServiceClient,
+    },
+    util::tendermint_proxy::v1::tendermint_proxy_service_client::TendermintProxyServiceClient,
+};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::time::timeout;
+use tonic::transport::{Channel, Endpoint, Uri};
 
-/// A client for the stake query service, which can be disconnected and reconnected in case of failures.
+use crate::tls::Tls;
+
+/// A client for the query services of a node, which can be disconnected and reconnected in case of
+/// failures.
 #[derive(Debug, Clone)]
 pub struct Client {
     uri: Uri,
-    inner: Arc<RwLock<Option<StakeQueryServiceClient<Channel>>>>,
+    tls: Option<Tls>,
+    inner: Arc<RwLock<Option<Channel>>>,
+    health: Arc<RwLock<Health>>,
+}
+
+/// The observed health of a single node.
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    /// Whether the node was reachable and responsive in the most recent round of updates, or `None`
+    /// if it has never been tried.
+    pub up: Option<bool>,
+    /// The highest block height the node has reported data for.
+    pub latest_height: Option<u64>,
+    /// The latency of the most recent successful request to the node.
+    pub latency: Option<Duration>,
+    /// The total number of errors of each kind encountered with the node.
+    pub errors: BTreeMap<ErrorKind, u64>,
+}
+
+/// The kind of an error encountered when talking to a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorKind {
+    /// The connection to the node could not be established.
+    Connect,
+    /// The node did not respond within the timeout.
+    Timeout,
+    /// The node responded with an RPC error.
+    Rpc,
+    /// The node responded, but the response was missing or malformed.
+    Invalid,
+}
+
+impl ErrorKind {
+    /// Classify an error which occurred while making a request to a node.
+    pub fn classify(error: &eyre::Report) -> Self {
+        if error
+            .downcast_ref::<tokio::time::error::Elapsed>()
+            .is_some()
+        {
+            ErrorKind::Timeout
+        } else if error.downcast_ref::<tonic::Status>().is_some() {
+            ErrorKind::Rpc
+        } else if error.downcast_ref::<tonic::transport::Error>().is_some() {
+            ErrorKind::Connect
+        } else {
+            ErrorKind::Invalid
+        }
+    }
+
+    /// Get the name of the error kind, as used in metric labels.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Connect => "connect",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Rpc => "rpc",
+            ErrorKind::Invalid => "invalid",
+        }
+    }
 }
 
 impl Client {
@@ -18,22 +85,49 @@ impl Client {
     pub fn new(uri: Uri) -> Self {
         Self {
             inner: Arc::new(RwLock::new(None)),
+            health: Arc::new(RwLock::new(Health::default())),
             uri,
+            tls: None,
         }
     }
 
+    /// Connect to the node with custom TLS settings, rather than the defaults.
+    pub fn with_tls(mut self, tls: Tls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
     /// Get the URI of the client.
     pub fn uri(&self) -> &Uri {
         &self.uri
     }
 
-    /// Connect the client to the server.
-    pub async fn connect(&self) -> eyre::Result<()> {
-        // If the client is already connected, this is a no-op.
-        if self.inner.read().is_none() {
-            let client = StakeQueryServiceClient::connect(self.uri.clone()).await?;
-            self.inner.write().replace(client);
+    /// Get the custom TLS settings of the client, if any.
+    pub fn tls(&self) -> Option<&Tls> {
+        self.tls.as_ref()
+    }
+
+    /// Connect the client to the server, giving up if connecting takes longer than the timeout.
+    ///
+    /// A new connection marks the node as up, and a failed one marks it as down; if the client is
+    /// already connected, this is a no-op, so that a failure earlier in the round still counts.
+    pub async fn connect(&self, connect_timeout: Duration) -> eyre::Result<()> {
+        if self.inner.read().is_some() {
+            return Ok(());
         }
+        let channel = async {
+            match &self.tls {
+                Some(tls) => tls.connect(&self.uri).await,
+                None => Ok(Endpoint::from(self.uri.clone()).connect().await?),
+            }
+        };
+        let channel = timeout(connect_timeout, channel)
+            .await
+            .map_err(eyre::Report::from)
+            .and_then(|channel| channel)
+            .inspect_err(|_| self.record_error(ErrorKind::Connect))?;
+        self.inner.write().replace(channel);
+        self.health.write().up = Some(true);
         Ok(())
     }
 
@@ -42,10 +136,54 @@ impl Client {
         self.inner.write().take();
     }
 
-    /// Get the client, if it is connected.
+    /// Get a client for the stake query service, if the client is connected.
     ///
     /// This does not attempt to connect the client if it is disconnected.
     pub fn get(&self) -> Option<StakeQueryServiceClient<Channel>> {
-        self.inner.read().clone()
+        self.inner.read().clone().map(StakeQueryServiceClient::new)
+    }
+
+    /// Get a client for the state commitment tree query service, if the client is connected.
+    ///
+    /// This does not attempt to connect the client if it is disconnected.
+    pub fn sct(&self) -> Option<SctQueryServiceClient<Channel>> {
+        self.inner.read().clone().map(SctQueryServiceClient::new)
+    }
+
+    /// Get a client for the node's proxy to its CometBFT RPC, if the client is connected.
+    ///
+    /// This does not attempt to connect the client if it is disconnected.
+    pub fn tendermint_proxy(&self) -> Option<TendermintProxyServiceClient<Channel>> {
+        self.inner
+            .read()
+            .clone()
+            .map(TendermintProxyServiceClient::new)
+    }
+
+    /// Check whether the client is currently connected.
+    pub fn is_connected(&self) -> bool {
+        self.inner.read().is_some()
+    }
+
+    /// Get a snapshot of the observed health of the node.
+    pub fn health(&self) -> Health {
+        self.health.read().clone()
+    }
+
+    /// Record a successful request to the node, which took the given time and returned data as of
+    /// the given height.
+    ///
+    /// This does not mark the node as up: a node is only up if every request in the round succeeds.
+    pub fn record_success(&self, latency: Duration, height: u64) {
+        let mut health = self.health.write();
+        health.latency = Some(latency);
+        health.latest_height = Some(health.latest_height.map_or(height, |h| h.max(height)));
+    }
+
+    /// Record an error encountered with the node, marking it as down.
+    pub fn record_error(&self, kind: ErrorKind) {
+        let mut health = self.health.write();
+        health.up = Some(false);
+        *health.errors.entry(kind).or_default() += 1;
     }
 }
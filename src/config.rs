@@ -0,0 +1,346 @@
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    fs,
+    signal::unix::{signal, SignalKind},
+    time::sleep,
+};
+use tonic::transport::Uri;
+
+use crate::{
+    alert::Rule, app::Mode, network::Networks, otlp::Protocol, report::Label, tls::Tls, Options,
+};
+
+/// How often to check the configuration file for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The contents of a configuration file, mirroring the command line [`Options`].
+///
+/// Every field is optional: lists are appended to those given on the command line, and single
+/// values override those given on the command line.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Validator identity keys to monitor for uptime.
+    #[serde(default)]
+    pub validators: Vec<String>,
+    /// Fullnode RPC endpoints to use as primary sources.
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+    /// Fullnode RPC endpoints to use as fallback sources, in order.
+    #[serde(default)]
+    pub fallbacks: Vec<Node>,
+    /// Webhook URLs to notify when an alert fires or resolves.
+    #[serde(default)]
+    pub webhooks: Vec<String>,
+    /// Alerting rules.
+    #[serde(default)]
+    pub alerts: Vec<Rule>,
+    /// Whether to monitor every validator on the chain.
+    pub all_validators: Option<bool>,
+    /// Whether to monitor only validators in the active set, when monitoring every validator.
+    pub active_only: Option<bool>,
+    /// Address on which to serve metrics (changes take effect only on restart).
+    pub bind: Option<String>,
+    /// Certificate file with which to serve HTTPS (changes take effect only on restart).
+    pub tls_cert: Option<PathBuf>,
+    /// Private key file of the certificate (changes take effect only on restart).
+    pub tls_key: Option<PathBuf>,
+    /// Bearer token required to access the server (changes take effect only on restart).
+    pub bearer_token: Option<String>,
+    /// `USER:PASSWORD` required to access the server (changes take effect only on restart).
+    pub basic_auth: Option<String>,
+    /// OpenTelemetry collector to push metrics to (changes take effect only on restart).
+    pub otlp_endpoint: Option<String>,
+    /// Protocol with which to push metrics to the collector (changes take effect only on restart).
+    pub otlp_protocol: Option<Protocol>,
+    /// Interval between pushes to the collector (changes take effect only on restart).
+    pub otlp_interval: Option<String>,
+    /// Pushgateway to push metrics to (changes take effect only on restart).
+    pub pushgateway: Option<String>,
+    /// Job name under which to push metrics to the Pushgateway (changes take effect only on
+    /// restart).
+    pub pushgateway_job: Option<String>,
+    /// Remote-write endpoint to push metrics to (changes take effect only on restart).
+    pub remote_write: Option<String>,
+    /// Interval between pushes (changes take effect only on restart).
+    pub push_interval: Option<String>,
+    /// `USER:PASSWORD` with which to push (changes take effect only on restart).
+    pub push_basic_auth: Option<String>,
+    /// When to update the metrics (changes take effect only on restart).
+    pub mode: Option<Mode>,
+    /// Minimum polling interval for updating the metrics.
+    pub poll_interval: Option<String>,
+    /// Timeout for connecting to each fullnode.
+    pub connect_timeout: Option<String>,
+    /// Maximum age of each validator's info for umbrella to report itself ready.
+    pub max_staleness: Option<String>,
+    /// How long the chain's height must stop advancing before the chain is considered halted.
+    pub halt_threshold: Option<String>,
+    /// File in which to save the latest info about each validator, to be restored on start.
+    pub state_file: Option<PathBuf>,
+    /// Prefix for the name of every metric.
+    pub namespace: Option<String>,
+    /// Constant labels to attach to every metric, by name.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Named networks to monitor, each with its own validators and nodes, by name.
+    #[serde(default)]
+    pub networks: BTreeMap<String, Network>,
+}
+
+/// A single named network in a configuration file, whose other options are shared with every
+/// network.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Network {
+    /// Validator identity keys to monitor for uptime.
+    #[serde(default)]
+    pub validators: Vec<String>,
+    /// Fullnode RPC endpoints to use as primary sources.
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+    /// Fullnode RPC endpoints to use as fallback sources, in order.
+    #[serde(default)]
+    pub fallbacks: Vec<Node>,
+    /// Whether to monitor every validator on the chain.
+    pub all_validators: Option<bool>,
+    /// Whether to monitor only validators in the active set, when monitoring every validator.
+    pub active_only: Option<bool>,
+    /// File in which to save the latest info about each validator, to be restored on start.
+    pub state_file: Option<PathBuf>,
+}
+
+impl Network {
+    /// Make the options for this network, sharing everything but validators and nodes with the
+    /// given options.
+    fn into_options(self, name: String, shared: &Options) -> eyre::Result<Options> {
+        let mut options = Options {
+            validator: parse_all("validators", self.validators)?,
+            node: Vec::new(),
+            fallback: Vec::new(),
+            tls: Default::default(),
+            all_validators: self.all_validators.unwrap_or(false),
+            active_only: self.active_only.unwrap_or(false),
+            state_file: self.state_file,
+            network: Some(name),
+            networks: Vec::new(),
+            ..shared.clone()
+        };
+        for node in self.nodes {
+            options.node.push(parse_node("nodes", node, &mut options)?);
+        }
+        for fallback in self.fallbacks {
+            options
+                .fallback
+                .push(parse_node("fallbacks", fallback, &mut options)?);
+        }
+        Ok(options)
+    }
+}
+
+/// A fullnode RPC endpoint in a configuration file: either just its URI, or a table of its URI and
+/// custom TLS settings.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Node {
+    Uri(String),
+    WithTls { uri: String, tls: Tls },
+}
+
+impl Config {
+    /// Read and parse a configuration file.
+    pub async fn read(path: &Path) -> eyre::Result<Self> {
+        let contents = fs::read_to_string(path).await?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Merge this configuration into the given command line options.
+    pub fn merge_into(self, mut options: Options) -> eyre::Result<Options> {
+        options
+            .validator
+            .extend(parse_all("validators", self.validators)?);
+        for node in self.nodes {
+            options.node.push(parse_node("nodes", node, &mut options)?);
+        }
+        for fallback in self.fallbacks {
+            options
+                .fallback
+                .push(parse_node("fallbacks", fallback, &mut options)?);
+        }
+        options
+            .webhook
+            .extend(parse_all("webhooks", self.webhooks)?);
+        options.alerts.extend(self.alerts);
+        if let Some(all_validators) = self.all_validators {
+            options.all_validators = all_validators;
+        }
+        if let Some(active_only) = self.active_only {
+            options.active_only = active_only;
+        }
+        if let Some(bind) = self.bind {
+            options.bind = parse("bind", bind)?;
+        }
+        if let Some(tls_cert) = self.tls_cert {
+            options.tls_cert = Some(tls_cert);
+        }
+        if let Some(tls_key) = self.tls_key {
+            options.tls_key = Some(tls_key);
+        }
+        if let Some(bearer_token) = self.bearer_token {
+            options.bearer_token = Some(bearer_token);
+        }
+        if let Some(basic_auth) = self.basic_auth {
+            // Don't use `parse`, which would log the password along with the error:
+            options.basic_auth = Some(
+                basic_auth
+                    .parse()
+                    .map_err(|error| eyre!("invalid value for `basic-auth`: {error}"))?,
+            );
+        }
+        if let Some(otlp_endpoint) = self.otlp_endpoint {
+            options.otlp_endpoint = Some(parse("otlp-endpoint", otlp_endpoint)?);
+        }
+        if let Some(otlp_protocol) = self.otlp_protocol {
+            options.otlp_protocol = otlp_protocol;
+        }
+        if let Some(otlp_interval) = self.otlp_interval {
+            options.otlp_interval = parse("otlp-interval", otlp_interval)?;
+        }
+        if let Some(pushgateway) = self.pushgateway {
+            options.pushgateway = Some(parse("pushgateway", pushgateway)?);
+        }
+        if let Some(pushgateway_job) = self.pushgateway_job {
+            options.pushgateway_job = pushgateway_job;
+        }
+        if let Some(remote_write) = self.remote_write {
+            options.remote_write = Some(parse("remote-write", remote_write)?);
+        }
+        if let Some(push_interval) = self.push_interval {
+            options.push_interval = parse("push-interval", push_interval)?;
+        }
+        if let Some(push_basic_auth) = self.push_basic_auth {
+            options.push_basic_auth = Some(
+                push_basic_auth
+                    .parse()
+                    .map_err(|error| eyre!("invalid value for `push-basic-auth`: {error}"))?,
+            );
+        }
+        if let Some(mode) = self.mode {
+            options.mode = mode;
+        }
+        if let Some(poll_interval) = self.poll_interval {
+            options.poll_interval = parse("poll-interval", poll_interval)?;
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            options.connect_timeout = parse("connect-timeout", connect_timeout)?;
+        }
+        if let Some(max_staleness) = self.max_staleness {
+            options.max_staleness = parse("max-staleness", max_staleness)?;
+        }
+        if let Some(halt_threshold) = self.halt_threshold {
+            options.halt_threshold = parse("halt-threshold", halt_threshold)?;
+        }
+        if let Some(state_file) = self.state_file {
+            options.state_file = Some(state_file);
+        }
+        if let Some(namespace) = self.namespace {
+            options.namespace = namespace;
+        }
+        for (name, value) in self.labels {
+            options
+                .label
+                .push(Label::new(name, value).map_err(|error| eyre!("invalid `labels`: {error}"))?);
+        }
+        // Each network shares the other options, so it can only be made once they're all merged:
+        for (name, network) in self.networks {
+            let network = network
+                .into_options(name.clone(), &options)
+                .map_err(|error| eyre!("in network `{name}`: {error}"))?;
+            options.networks.push(network);
+        }
+        Ok(options)
+    }
+}
+
+/// Parse a single configuration value, mentioning the field name in any error.
+fn parse<T>(field: &str, value: String) -> eyre::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|error| eyre!("invalid value {value:?} for `{field}`: {error}"))
+}
+
+/// Parse a list of configuration values, mentioning the field name in any error.
+fn parse_all<T>(field: &str, values: Vec<String>) -> eyre::Result<Vec<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    values
+        .into_iter()
+        .map(|value| parse(field, value))
+        .collect()
+}
+
+/// Parse a single node, recording its TLS settings in the options if it has any.
+fn parse_node(field: &str, node: Node, options: &mut Options) -> eyre::Result<Uri> {
+    match node {
+        Node::Uri(uri) => parse(field, uri),
+        Node::WithTls { uri, tls } => {
+            let uri: Uri = parse(field, uri)?;
+            tls.validate()
+                .map_err(|error| eyre!("invalid TLS settings for {uri} in `{field}`: {error}"))?;
+            options.tls.insert(uri.clone(), tls);
+            Ok(uri)
+        }
+    }
+}
+
+/// Watch the configuration file forever, reconfiguring the application whenever it changes or the
+/// process receives SIGHUP.
+///
+/// The given command line options are used as the base for each reload. If the configuration file
+/// cannot be read or is invalid, the error is logged and the previous configuration stays in
+/// effect.
+pub async fn watch(path: PathBuf, cli: Options, networks: Networks) -> eyre::Result<Infallible> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut last_modified = modified(&path).await;
+
+    loop {
+        select! {
+            _ = hangup.recv() => {
+                info!(path = %path.display(), "received SIGHUP, reloading configuration");
+            }
+            _ = sleep(WATCH_INTERVAL) => {
+                if modified(&path).await == last_modified {
+                    continue;
+                }
+                info!(path = %path.display(), "configuration file changed, reloading");
+            }
+        }
+        last_modified = modified(&path).await;
+
+        match cli.clone().load().await {
+            Ok(options) => networks.reconfigure(options),
+            Err(error) => {
+                error!(path = %path.display(), %error, "failed to reload configuration, keeping previous configuration");
+            }
+        }
+    }
+}
+
+/// Get the modification time of a file, if it can be determined.
+async fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).await.ok()?.modified().ok()
+}
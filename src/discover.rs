@@ -0,0 +1,112 @@
+use penumbra_proto::core::component::stake::v1::ValidatorInfoRequest;
+use penumbra_stake::{validator, IdentityKey};
+use std::time::Duration;
+use tokio::time::timeout;
+
+use crate::{client::ErrorKind, Client};
+
+/// Whether and which validators to discover automatically from the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Discovery {
+    /// Only monitor the validators specified explicitly.
+    #[default]
+    Off,
+    /// Monitor every validator known to the chain, whatever its state.
+    All,
+    /// Monitor only the validators in the active set.
+    ActiveOnly,
+}
+
+impl Discovery {
+    /// Select the identity keys of the validators in the validator set which should be monitored
+    /// according to this discovery setting.
+    ///
+    /// Returns `None` if discovery is off.
+    pub fn select(&self, validator_set: &[validator::Info]) -> Option<Vec<IdentityKey>> {
+        let active_only = match self {
+            Discovery::Off => return None,
+            Discovery::All => false,
+            Discovery::ActiveOnly => true,
+        };
+        Some(
+            validator_set
+                .iter()
+                .filter(|info| !active_only || info.status.state == validator::State::Active)
+                .map(|info| info.validator.identity_key)
+                .collect(),
+        )
+    }
+}
+
+/// Get the total voting power of the active validators in the validator set.
+pub fn total_voting_power(validator_set: &[validator::Info]) -> u64 {
+    validator_set
+        .iter()
+        .filter(|info| info.status.state == validator::State::Active)
+        .map(|info| info.status.voting_power.value())
+        .sum::<u128>()
+        .try_into()
+        .expect("total voting power is too large to fit in u64")
+}
+
+/// Get the info for every validator in the validator set (or only the active ones, if inactive ones
+/// are not requested), asking each of the given nodes in turn until one of them answers.
+///
+/// Returns `None` if no node could answer.
+pub async fn validator_set(
+    nodes: &[Client],
+    show_inactive: bool,
+    connect_timeout: Duration,
+) -> Option<Vec<validator::Info>> {
+    for client in nodes {
+        if let Err(error) = client.connect(connect_timeout).await {
+            warn!(node = %client.uri(), "{}", error);
+            continue;
+        }
+
+        match timeout(connect_timeout, validator_set_from(client, show_inactive)).await {
+            Ok(Ok(validators)) => return Some(validators),
+            Ok(Err(error)) => {
+                client.disconnect();
+                client.record_error(ErrorKind::classify(&error));
+                warn!(node = %client.uri(), "failed to get validator set: {}", error);
+            }
+            Err(elapsed) => {
+                client.disconnect();
+                client.record_error(ErrorKind::Timeout);
+                warn!(node = %client.uri(), "failed to get validator set: {}", elapsed);
+            }
+        }
+    }
+
+    error!("failed to get validator set from any primary node");
+    None
+}
+
+/// Get the info for every validator in the validator set from a single node.
+async fn validator_set_from(
+    client: &Client,
+    show_inactive: bool,
+) -> eyre::Result<Vec<validator::Info>> {
+    let mut stake_client = client
+        .get()
+        .ok_or_else(|| eyre!("client is not connected"))?;
+
+    let mut stream = stake_client
+        .validator_info(ValidatorInfoRequest { show_inactive })
+        .await?
+        .into_inner();
+
+    let mut validators = Vec::new();
+    while let Some(response) = stream.message().await? {
+        validators.push(
+            response
+                .validator_info
+                .ok_or_else(|| eyre!("no validator info"))?
+                .try_into()
+                .map_err(|_| eyre!("invalid validator info"))?,
+        );
+    }
+
+    Ok(validators)
+}
@@ -0,0 +1,273 @@
+use prometheus::{
+    proto::{Metric, MetricFamily, MetricType},
+    Encoder, ProtobufEncoder, TextEncoder,
+};
+use std::{fmt::Write as _, io::Write};
+
+/// The content type of the OpenMetrics text format.
+pub const OPENMETRICS_FORMAT: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Gauges which encode one of a fixed set of states as a number, along with the names of the states
+/// in order, to be exposed as OpenMetrics state sets.
+const STATESETS: &[(&str, &[&str])] = &[
+    (
+        "state",
+        &[
+            "Defined",
+            "Disabled",
+            "Inactive",
+            "Active",
+            "Jailed",
+            "Tombstoned",
+        ],
+    ),
+    ("bonding_state", &["Bonded", "Unbonding", "Unbonded"]),
+];
+
+/// Units which metric names may end with, to be declared in the OpenMetrics format.
+const UNITS: &[&str] = &["seconds"];
+
+/// A format in which metrics can be exposed to scrapers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The classic Prometheus text format.
+    Text,
+    /// The OpenMetrics text format.
+    OpenMetrics,
+    /// The length-delimited Prometheus protobuf format.
+    Protobuf,
+}
+
+impl Format {
+    /// Pick the format most preferred by a scraper according to its `Accept` header, falling back
+    /// to the classic text format if the header is missing or accepts nothing more specific.
+    pub fn negotiate(accept: Option<&str>) -> Self {
+        let mut best = (Format::Text, 0.0);
+        for range in accept.unwrap_or_default().split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+            let mut quality = 1.0;
+            let mut proto = None;
+            let mut encoding = None;
+            for param in params {
+                let Some((name, value)) = param.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"');
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "q" => quality = value.parse().unwrap_or(0.0),
+                    "proto" => proto = Some(value),
+                    "encoding" => encoding = Some(value),
+                    _ => {}
+                }
+            }
+
+            let format = match media_type.as_str() {
+                "application/openmetrics-text" => Format::OpenMetrics,
+                "application/vnd.google.protobuf"
+                    if proto == Some("io.prometheus.client.MetricFamily")
+                        && encoding == Some("delimited") =>
+                {
+                    Format::Protobuf
+                }
+                "text/plain" | "text/*" | "*/*" => Format::Text,
+                _ => continue,
+            };
+            // The first of equally preferred formats wins:
+            if quality > best.1 {
+                best = (format, quality);
+            }
+        }
+        best.0
+    }
+
+    /// Get the content type of the format, for the `Content-Type` header.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Text => "text/plain; version=0.0.4; charset=utf-8",
+            Format::OpenMetrics => OPENMETRICS_FORMAT,
+            Format::Protobuf => prometheus::PROTOBUF_FORMAT,
+        }
+    }
+
+    /// Encode the metrics, whose names are prefixed with the given namespace, in the format.
+    pub fn encode(
+        &self,
+        families: &[MetricFamily],
+        namespace: &str,
+    ) -> prometheus::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        match self {
+            Format::Text => TextEncoder::new().encode(families, &mut buffer)?,
+            Format::OpenMetrics => {
+                OpenMetricsEncoder::new(namespace).encode(families, &mut buffer)?
+            }
+            Format::Protobuf => ProtobufEncoder::new().encode(families, &mut buffer)?,
+        }
+        Ok(buffer)
+    }
+}
+
+/// An encoder for the OpenMetrics text format.
+///
+/// Beyond the classic text format, this declares units, exposes `_info` gauges as info metrics,
+/// and exposes gauges encoding a state as a number as state sets.
+#[derive(Debug, Clone, Default)]
+pub struct OpenMetricsEncoder {
+    /// The prefix of the names of the metrics, to be ignored when recognizing state sets.
+    namespace: String,
+}
+
+impl OpenMetricsEncoder {
+    /// Make an encoder for metrics whose names are prefixed with the given namespace.
+    pub fn new(namespace: &str) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+        }
+    }
+}
+
+impl Encoder for OpenMetricsEncoder {
+    fn encode<W: Write>(
+        &self,
+        families: &[MetricFamily],
+        writer: &mut W,
+    ) -> prometheus::Result<()> {
+        let mut out = String::new();
+        for family in families {
+            encode_family(family, &self.namespace, &mut out);
+        }
+        out.push_str("# EOF\n");
+        writer.write_all(out.as_bytes())?;
+        Ok(())
+    }
+
+    fn format_type(&self) -> &str {
+        OPENMETRICS_FORMAT
+    }
+}
+
+/// The OpenMetrics type of a metric family.
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Counter,
+    Gauge,
+    Info,
+    StateSet(&'static [&'static str]),
+    Unknown,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Info => "info",
+            Kind::StateSet(_) => "stateset",
+            Kind::Unknown => "unknown",
+        }
+    }
+}
+
+/// Encode a single metric family in the OpenMetrics text format.
+fn encode_family(family: &MetricFamily, namespace: &str, out: &mut String) {
+    let name = family.get_name();
+    let metrics = family.get_metric();
+    let states = name.strip_prefix(namespace).and_then(stateset);
+
+    // OpenMetrics names counter and info families without their sample suffixes:
+    let (kind, family_name) = match family.get_field_type() {
+        MetricType::COUNTER => (Kind::Counter, name.strip_suffix("_total").unwrap_or(name)),
+        MetricType::GAUGE => match (states, name.strip_suffix("_info")) {
+            (Some(states), _) => (Kind::StateSet(states), name),
+            (None, Some(base))
+                if metrics
+                    .iter()
+                    .all(|metric| metric.get_gauge().get_value() == 1.0) =>
+            {
+                (Kind::Info, base)
+            }
+            _ => (Kind::Gauge, name),
+        },
+        MetricType::UNTYPED => (Kind::Unknown, name),
+        // Nothing in umbrella is a histogram or summary:
+        MetricType::HISTOGRAM | MetricType::SUMMARY => {
+            warn!(%name, "cannot expose histograms or summaries as OpenMetrics");
+            return;
+        }
+    };
+
+    let _ = writeln!(out, "# TYPE {family_name} {}", kind.as_str());
+    if let Some(unit) = UNITS
+        .iter()
+        .find(|unit| family_name.ends_with(&format!("_{unit}")))
+    {
+        let _ = writeln!(out, "# UNIT {family_name} {unit}");
+    }
+    if !family.get_help().is_empty() {
+        let _ = writeln!(out, "# HELP {family_name} {}", escape(family.get_help()));
+    }
+
+    for metric in metrics {
+        match kind {
+            Kind::Counter => {
+                let value = metric.get_counter().get_value();
+                sample(out, &format!("{family_name}_total"), metric, None, value);
+            }
+            Kind::Gauge => sample(out, name, metric, None, metric.get_gauge().get_value()),
+            Kind::Info => sample(out, name, metric, None, 1.0),
+            Kind::StateSet(states) => {
+                // Each state is a sample labeled with the family's name, and only the current state
+                // is set:
+                let value = metric.get_gauge().get_value();
+                for (index, state) in states.iter().enumerate() {
+                    let current = f64::from(u8::from(index as f64 == value));
+                    sample(out, name, metric, Some((name, state)), current);
+                }
+            }
+            Kind::Unknown => sample(out, name, metric, None, metric.get_untyped().get_value()),
+        }
+    }
+}
+
+/// Get the names of the states of a gauge encoding a state as a number, if it is one.
+fn stateset(name: &str) -> Option<&'static [&'static str]> {
+    STATESETS
+        .iter()
+        .find(|(stateset, _)| *stateset == name)
+        .map(|(_, states)| *states)
+}
+
+/// Encode a single sample, with an extra label if given.
+fn sample(out: &mut String, name: &str, metric: &Metric, extra: Option<(&str, &str)>, value: f64) {
+    let labels = metric
+        .get_label()
+        .iter()
+        .map(|label| (label.get_name(), label.get_value()))
+        .chain(extra)
+        .map(|(name, value)| format!("{name}=\"{}\"", escape(value)))
+        .collect::<Vec<_>>();
+    out.push_str(name);
+    if !labels.is_empty() {
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+    let _ = writeln!(out, " {}", number(value));
+}
+
+/// Escape a label value or help text.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Format a number as OpenMetrics expects, including infinities.
+fn number(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,118 @@
+use penumbra_stake::Uptime;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, time::SystemTime};
+
+/// The maximum number of incidents remembered for each validator; older incidents are forgotten,
+/// but still counted in the total.
+const MAX_INCIDENTS: usize = 1000;
+
+/// A downtime incident: a run of consecutive blocks missed by a validator.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Incident {
+    /// The first block missed.
+    pub start_height: u64,
+    /// The last block missed so far.
+    pub end_height: u64,
+    /// Whether the validator was still missing blocks as of the latest uptime snapshot.
+    pub ongoing: bool,
+    /// When the incident was first observed.
+    pub detected_at: SystemTime,
+    /// When the incident was first observed to have ended, if it has.
+    pub resolved_at: Option<SystemTime>,
+}
+
+impl Incident {
+    /// Get the number of blocks missed in the incident so far.
+    pub fn blocks_missed(&self) -> u64 {
+        self.end_height - self.start_height + 1
+    }
+}
+
+/// The downtime incidents of a single validator, derived from successive uptime snapshots.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Incidents {
+    /// The most recent incidents, oldest first.
+    incidents: VecDeque<Incident>,
+    /// The total number of incidents observed to begin since the first uptime snapshot, including
+    /// those forgotten.
+    total: u64,
+    /// The height as of which the latest uptime snapshot was taken, if any has been observed.
+    #[serde(default)]
+    as_of_height: Option<u64>,
+}
+
+impl Incidents {
+    /// Get the most recent incidents, oldest first.
+    pub fn list(&self) -> Vec<Incident> {
+        self.incidents.iter().cloned().collect()
+    }
+
+    /// Get the total number of incidents observed to begin since the first uptime snapshot.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Derive incidents from an uptime snapshot, extending incidents already known and recording
+    /// new ones.
+    ///
+    /// A run of missed blocks continuing (or overlapping) a known incident is the same incident, so
+    /// observing overlapping snapshots, or the same snapshot again, is harmless. Incidents in the
+    /// first snapshot are recorded but not counted, since they began before monitoring did.
+    pub fn observe(&mut self, uptime: &Uptime) {
+        let now = SystemTime::now();
+        let as_of_height = uptime.as_of_height();
+        let previous = self.as_of_height.replace(as_of_height);
+
+        // Split the missed blocks in the window into runs of consecutive heights:
+        let mut runs: Vec<(u64, u64)> = Vec::new();
+        for height in uptime.missed_blocks() {
+            match runs.last_mut() {
+                Some((_, end)) if *end + 1 == height => *end = height,
+                _ => runs.push((height, height)),
+            }
+        }
+
+        for (start, end) in runs {
+            let ongoing = end == as_of_height;
+            match self
+                .incidents
+                .iter_mut()
+                .find(|incident| incident.start_height <= start && start <= incident.end_height + 1)
+            {
+                Some(incident) => {
+                    incident.end_height = incident.end_height.max(end);
+                    if incident.ongoing && !ongoing {
+                        incident.resolved_at = Some(now);
+                    }
+                    incident.ongoing = ongoing;
+                }
+                // A run of blocks already observed which matches no known incident was forgotten to
+                // make room for more recent ones, so it mustn't be recorded or counted again:
+                None if previous.is_some_and(|previous| start <= previous) => {}
+                None => {
+                    if previous.is_some() {
+                        self.total += 1;
+                    }
+                    self.incidents.push_back(Incident {
+                        start_height: start,
+                        end_height: end,
+                        ongoing,
+                        detected_at: now,
+                        resolved_at: (!ongoing).then_some(now),
+                    });
+                    if self.incidents.len() > MAX_INCIDENTS {
+                        self.incidents.pop_front();
+                    }
+                }
+            }
+        }
+
+        // Any incident still marked ongoing which did not continue up to this snapshot has ended:
+        for incident in self.incidents.iter_mut() {
+            if incident.ongoing && incident.end_height < as_of_height {
+                incident.ongoing = false;
+                incident.resolved_at = Some(now);
+            }
+        }
+    }
+}
@@ -1,23 +1,122 @@
 use parking_lot::RwLock;
-use penumbra_stake::{validator, IdentityKey, Uptime};
-use std::sync::Arc;
+use penumbra_stake::{rate::RateData, validator, FundingStream, IdentityKey, Uptime};
+use serde::{Deserialize, Serialize};
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::time::Instant;
+use tonic::transport::Uri;
+
+use crate::{
+    incident::{Incident, Incidents},
+    penalty::Penalties,
+};
+
+/// The fixed-point denominator of on-chain rates: a rate of this many units is a rate of 1.
+const RATE_DENOMINATOR: f64 = 100_000_000.0;
 
 /// An updateable cell holding the latest information about a validator.
 #[derive(Debug, Clone)]
 pub struct Latest {
     /// The identity key of the validator.
     identity: IdentityKey,
+    /// The operator-supplied alias for the validator, if any.
+    alias: Option<String>,
     /// The latest information about the validator.
     info: Arc<RwLock<Option<Info>>>,
+    /// The slashing penalties applied to the validator.
+    penalties: Arc<RwLock<Penalties>>,
+    /// The downtime incidents of the validator.
+    incidents: Arc<RwLock<Incidents>>,
+    /// The responses about the validator from each node in the current round of updates.
+    observations: Arc<RwLock<Vec<Observation>>>,
+    /// Whether nodes disagreed about the validator in the most recent round of updates.
+    disagreement: Arc<AtomicBool>,
+}
+
+/// The lasting information about a validator, as saved to and restored from the state file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Saved {
+    /// The identity key of the validator.
+    pub identity: IdentityKey,
+    /// The on-chain definition of the validator, if any node has reported it.
+    definition: Option<validator::Validator>,
+    /// The status of the validator.
+    status: validator::Status,
+    /// The uptime of the validator.
+    uptime: Uptime,
+    /// The current rate data of the validator, if any node has reported it.
+    rate_data: Option<RateData>,
+    /// The node the info was last updated from.
+    source: String,
+    /// When the info was last updated from a node.
+    updated_at: SystemTime,
+    /// The slashing penalties applied to the validator.
+    penalties: Penalties,
+    /// The downtime incidents of the validator.
+    #[serde(default)]
+    incidents: Incidents,
+}
+
+/// A response about a validator from a single node, kept for comparison with the responses of the
+/// other nodes.
+#[derive(Debug, Clone)]
+pub struct Observation {
+    /// The node which responded.
+    pub node: Uri,
+    /// The status of the validator, according to the node.
+    pub status: validator::Status,
+    /// The uptime of the validator, according to the node.
+    pub uptime: Uptime,
+}
+
+/// A validator to monitor, as specified by the operator: an identity key, optionally followed by
+/// `=` and an alias, like `penumbravalid1...=my-validator`.
+#[derive(Debug, Clone)]
+pub struct Spec {
+    /// The identity key of the validator.
+    pub identity: IdentityKey,
+    /// The alias for the validator, if any.
+    pub alias: Option<String>,
+}
+
+impl FromStr for Spec {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        let (identity, alias) = match s.split_once('=') {
+            Some((identity, alias)) => (identity, Some(alias.to_string())),
+            None => (s, None),
+        };
+        Ok(Self {
+            identity: identity
+                .parse()
+                .map_err(|error| eyre!("invalid validator identity key: {error}"))?,
+            alias,
+        })
+    }
 }
 
 /// The latest information about a validator.
 #[derive(Debug, Clone)]
 pub struct Info {
+    /// The on-chain definition of the validator, if any node has reported it.
+    definition: Option<validator::Validator>,
     /// The status of the validator.
     status: validator::Status,
     /// The uptime of the validator.
     uptime: Uptime,
+    /// The current rate data of the validator, if any node has reported it.
+    rate_data: Option<RateData>,
+    /// The node the info was last updated from.
+    source: Uri,
+    /// When the info was last updated from a node.
+    updated_at: Instant,
     /// Whether the info has been updated since the last time it was reported.
     ///
     /// This is used for determining whether the update process was successful, in the case of
@@ -26,13 +125,18 @@ pub struct Info {
 }
 
 impl Latest {
-    /// Make a new updateable cell for the given validator identity.
+    /// Make a new updateable cell for the given validator.
     ///
     /// The cell is initially empty and must be updated before use.
-    pub fn new(identity: IdentityKey) -> Self {
+    pub fn new(Spec { identity, alias }: Spec) -> Self {
         Self {
             identity,
+            alias,
             info: Arc::new(RwLock::new(None)),
+            penalties: Arc::new(RwLock::new(Penalties::default())),
+            incidents: Arc::new(RwLock::new(Incidents::default())),
+            observations: Arc::new(RwLock::new(Vec::new())),
+            disagreement: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -41,6 +145,35 @@ impl Latest {
         self.identity
     }
 
+    /// Get the operator-supplied alias of the validator, if any.
+    pub fn alias(&self) -> Option<&str> {
+        self.alias.as_deref()
+    }
+
+    /// Set the operator-supplied alias of the validator.
+    ///
+    /// The alias is not shared with other clones of this cell.
+    pub fn set_alias(&mut self, alias: Option<String>) {
+        self.alias = alias;
+    }
+
+    /// Get a human-readable name for the validator: its alias if it has one, or else its on-chain
+    /// name if known, or else its identity key.
+    pub fn name(&self) -> String {
+        self.alias()
+            .map(str::to_string)
+            .or_else(|| self.definition().map(|definition| definition.name))
+            .unwrap_or_else(|| self.identity().to_string())
+    }
+
+    /// Get the on-chain definition of the validator.
+    pub fn definition(&self) -> Option<validator::Validator> {
+        self.info
+            .read()
+            .as_ref()
+            .and_then(|info| info.definition.clone())
+    }
+
     /// Get the status of the validator.
     fn status(&self) -> Option<validator::Status> {
         self.info.read().as_ref().map(|info| info.status.clone())
@@ -72,7 +205,168 @@ impl Latest {
         self.info.read().as_ref().map(|info| info.uptime.clone())
     }
 
-    /// Reset the updated flag to false.
+    /// Get the current rate data of the validator.
+    pub fn rate_data(&self) -> Option<RateData> {
+        self.info
+            .read()
+            .as_ref()
+            .and_then(|info| info.rate_data.clone())
+    }
+
+    /// Get the exchange rate of the validator's delegation token to the staking token.
+    pub fn exchange_rate(&self) -> Option<f64> {
+        self.rate_data()
+            .map(|rate_data| rate_data.validator_exchange_rate.value() as f64 / RATE_DENOMINATOR)
+    }
+
+    /// Get the reward rate of the validator for the current epoch.
+    pub fn reward_rate(&self) -> Option<f64> {
+        self.rate_data()
+            .map(|rate_data| rate_data.validator_reward_rate.value() as f64 / RATE_DENOMINATOR)
+    }
+
+    /// Get the validator's funding streams, as pairs of the recipient (an address, or
+    /// `community-pool`) and the rate in basis points.
+    pub fn funding_streams(&self) -> Option<Vec<(String, u16)>> {
+        self.definition().map(|definition| {
+            definition
+                .funding_streams
+                .iter()
+                .map(|stream| match stream {
+                    FundingStream::ToAddress { address, rate_bps } => {
+                        (address.to_string(), *rate_bps)
+                    }
+                    FundingStream::ToCommunityPool { rate_bps } => {
+                        ("community-pool".to_string(), *rate_bps)
+                    }
+                })
+                .collect()
+        })
+    }
+
+    /// Get the validator's total commission in basis points, summed over all its funding streams.
+    ///
+    /// The sum is wider than each stream's rate, since a (misconfigured) validator's streams may add
+    /// up to more than `u16::MAX`.
+    pub fn commission_bps(&self) -> Option<u32> {
+        self.funding_streams().map(|streams| {
+            streams
+                .iter()
+                .map(|&(_, rate_bps)| u32::from(rate_bps))
+                .sum()
+        })
+    }
+
+    /// Get the state of the validator as a number, in order of the lifecycle of a validator:
+    /// 0=Defined, 1=Disabled, 2=Inactive, 3=Active, 4=Jailed, 5=Tombstoned.
+    pub fn state_number(&self) -> Option<u8> {
+        self.state().map(|state| {
+            use validator::State::*;
+            match state {
+                Defined => 0,
+                Disabled => 1,
+                Inactive => 2,
+                Active => 3,
+                Jailed => 4,
+                Tombstoned => 5,
+            }
+        })
+    }
+
+    /// Get the bonding state of the validator as a number: 0=Bonded, 1=Unbonding, 2=Unbonded.
+    pub fn bonding_state_number(&self) -> Option<u8> {
+        self.bonding_state().map(|bonding_state| {
+            use validator::BondingState::*;
+            match bonding_state {
+                Bonded => 0,
+                Unbonding { .. } => 1,
+                Unbonded => 2,
+            }
+        })
+    }
+
+    /// Get the height at which the validator's delegation pool finishes unbonding, if it is
+    /// currently unbonding.
+    pub fn unbonds_at_height(&self) -> Option<u64> {
+        match self.bonding_state()? {
+            validator::BondingState::Unbonding { unbonds_at_height } => Some(unbonds_at_height),
+            _ => None,
+        }
+    }
+
+    /// Get the uptime of the validator as a percentage, computed over the block window considered
+    /// for on-chain uptime calculation.
+    pub fn uptime_percent(&self) -> Option<f64> {
+        self.uptime().map(|uptime| {
+            let downtime_fraction =
+                uptime.num_missed_blocks() as f64 / uptime.missed_blocks_window() as f64;
+            let uptime_fraction = 1.0 - downtime_fraction;
+            uptime_fraction * 100.0
+        })
+    }
+
+    /// Get the number of most-recent consecutive blocks missed by the validator.
+    pub fn consecutive_missed_blocks(&self) -> Option<usize> {
+        self.uptime().map(|uptime| {
+            let mut block = uptime.as_of_height() + 1;
+            uptime
+                .missed_blocks()
+                .rev()
+                .take_while(|&b| {
+                    let consecutive = b + 1 == block;
+                    block = b;
+                    consecutive
+                })
+                .count()
+        })
+    }
+
+    /// Get the slashing penalties applied to the validator.
+    pub fn penalties(&self) -> Penalties {
+        self.penalties.read().clone()
+    }
+
+    /// Replace the slashing penalties applied to the validator.
+    pub fn set_penalties(&self, penalties: Penalties) {
+        *self.penalties.write() = penalties;
+    }
+
+    /// Get the most recent downtime incidents of the validator, oldest first.
+    pub fn incidents(&self) -> Vec<Incident> {
+        self.incidents.read().list()
+    }
+
+    /// Get the total number of downtime incidents of the validator ever observed.
+    pub fn total_incidents(&self) -> u64 {
+        self.incidents.read().total()
+    }
+
+    /// Get the height as of which the info for the validator was last reported, if it ever was.
+    pub fn as_of_height(&self) -> Option<u64> {
+        self.uptime().map(|uptime| uptime.as_of_height())
+    }
+
+    /// Get the node the info was last updated from.
+    pub fn source(&self) -> Option<Uri> {
+        self.info.read().as_ref().map(|info| info.source.clone())
+    }
+
+    /// Get the responses about the validator from each node in the current round of updates.
+    pub fn observations(&self) -> Vec<Observation> {
+        self.observations.read().clone()
+    }
+
+    /// Check whether nodes disagreed about the validator in the most recent round of updates.
+    pub fn disagreement(&self) -> bool {
+        self.disagreement.load(Ordering::SeqCst)
+    }
+
+    /// Record whether nodes disagreed about the validator in the most recent round of updates.
+    pub fn set_disagreement(&self, disagreement: bool) {
+        self.disagreement.store(disagreement, Ordering::SeqCst);
+    }
+
+    /// Reset the updated flag to false, and forget the responses from the previous round.
     ///
     /// This should be done at the start of each update cycle.
     pub fn reset(&self) {
@@ -80,29 +374,110 @@ impl Latest {
         if let Some(info) = info.as_mut() {
             info.updated = false;
         }
+        self.observations.write().clear();
     }
 
-    /// Update the info for the validator with the given status and uptime.
+    /// Update the info for the validator with the given definition, status, uptime and rate data,
+    /// as reported by the given node.
     ///
-    /// If the uptime reports an older height, no update to either field is made.
-    pub fn update(&self, status: validator::Status, uptime: Uptime) {
+    /// The response is kept for comparison with other nodes, but if the uptime reports an older
+    /// height, no update to any field is made. If the node couldn't report the definition and rate
+    /// data, the previously known ones are kept.
+    pub fn update(
+        &self,
+        source: &Uri,
+        definition: Option<validator::Validator>,
+        status: validator::Status,
+        uptime: Uptime,
+        rate_data: Option<RateData>,
+    ) {
+        self.observations.write().push(Observation {
+            node: source.clone(),
+            status: status.clone(),
+            uptime: uptime.clone(),
+        });
+
         // If the uptime is newer or equal, update all the fields; if the uptime is older, do
         // nothing, so that we only progress monotonically through time:
         let mut info = self.info.write();
         if info.as_ref().map_or(true, |info| {
             uptime.as_of_height() >= info.uptime.as_of_height()
         }) {
+            self.incidents.write().observe(&uptime);
+            let previous = info.take();
             *info = Some(Info {
+                definition: definition
+                    .or_else(|| previous.as_ref().and_then(|info| info.definition.clone())),
                 status,
                 uptime,
+                rate_data: rate_data.or_else(|| previous.and_then(|info| info.rate_data)),
+                source: source.clone(),
+                updated_at: Instant::now(),
                 updated: true,
             });
         }
     }
 
+    /// Save the lasting information about the validator, if there is any.
+    pub fn save(&self) -> Option<Saved> {
+        let info = self.info.read().clone()?;
+        Some(Saved {
+            identity: self.identity,
+            definition: info.definition,
+            status: info.status,
+            uptime: info.uptime,
+            rate_data: info.rate_data,
+            source: info.source.to_string(),
+            updated_at: SystemTime::now() - info.updated_at.elapsed(),
+            penalties: self.penalties(),
+            incidents: self.incidents.read().clone(),
+        })
+    }
+
+    /// Restore saved information about the validator, if there is no information yet.
+    ///
+    /// The restored info keeps its original age, and is stale until it is confirmed by a node.
+    pub fn restore(&self, saved: Saved) -> eyre::Result<()> {
+        ensure!(
+            saved.identity == self.identity,
+            "saved info is for another validator"
+        );
+        let source = saved.source.parse()?;
+
+        let mut info = self.info.write();
+        if info.is_some() {
+            return Ok(());
+        }
+        let age = SystemTime::now()
+            .duration_since(saved.updated_at)
+            .unwrap_or_default();
+        *info = Some(Info {
+            definition: saved.definition,
+            status: saved.status,
+            uptime: saved.uptime,
+            rate_data: saved.rate_data,
+            source,
+            updated_at: Instant::now().checked_sub(age).unwrap_or_else(Instant::now),
+            updated: false,
+        });
+        self.set_penalties(saved.penalties);
+        *self.incidents.write() = saved.incidents;
+        Ok(())
+    }
+
+    /// Get the time elapsed since the info was last updated from a node, if it ever was.
+    pub fn age(&self) -> Option<Duration> {
+        self.info
+            .read()
+            .as_ref()
+            .map(|info| info.updated_at.elapsed())
+    }
+
     /// Check whether the info is fresh.
+    ///
+    /// Info which has never been updated is not fresh.
     pub fn is_fresh(&self) -> bool {
-        self.info.read().as_ref().map_or(true, |info| info.updated)
+        self.info.read().as_ref().is_some_and(|info| info.updated)
     }
 
     /// Check whether the info is stale.
@@ -8,17 +8,38 @@ extern crate tokio;
 extern crate metrics;
 
 use clap::Parser;
-use std::{convert::Infallible, env};
+use std::{convert::Infallible, env, io, process};
+use tracing_subscriber::EnvFilter;
 
+mod alert;
+mod api;
 mod app;
+mod auth;
+mod chain;
+mod check;
 mod client;
+mod config;
+mod discover;
+mod exposition;
+mod incident;
 mod latest;
+mod network;
 mod options;
+mod otlp;
+mod penalty;
+mod persist;
+mod push;
 mod report;
 mod serve;
+mod status;
+mod tls;
 mod update;
 
-use app::App;
+#[cfg(test)]
+mod tests;
+
+use app::{App, Mode};
+use options::{Cli, Command};
 pub use {client::Client, latest::Latest, options::Options};
 pub use {report::report, serve::serve, update::update};
 
@@ -27,12 +48,75 @@ async fn main() -> eyre::Result<Infallible> {
     if env::var("RUST_LOG").is_err() {
         env::set_var("RUST_LOG", "warn");
     }
-    tracing_subscriber::fmt::init();
-    metrics_prometheus::install();
-
-    // Read the command line options and convert them into an initial application state:
-    let options = Options::parse();
-    let bind = options.bind;
-    let app = options.into_app();
-    serve(bind, app).await
+
+    match Cli::parse().command() {
+        Command::Serve(options) => {
+            tracing_subscriber::fmt::init();
+            run(options).await
+        }
+        Command::Check(check) => {
+            // The plugin's output is read from stdout, so logs must go elsewhere:
+            init_stderr_logging();
+            let (outcome, line) = check.run().await;
+            println!("{line}");
+            process::exit(outcome.code())
+        }
+        Command::Status(status) => {
+            init_stderr_logging();
+            print!("{}", status.run().await?);
+            process::exit(0)
+        }
+    }
+}
+
+/// Log to stderr rather than stdout, for commands whose output is meant to be read.
+fn init_stderr_logging() {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(io::stderr)
+        .init();
+}
+
+/// Serve metrics according to the given command line options, until the server fails.
+async fn run(cli: Options) -> eyre::Result<Infallible> {
+    // Read the configuration file, and convert the options into an initial application state:
+    let options = cli.clone().load().await?;
+    let server = options.server()?;
+    let otlp = options.otlp()?;
+    let pushers = options.pushers()?;
+    let networks = options.into_networks();
+
+    // Restore the state saved before the last restart, if any, so that metrics are served
+    // immediately (albeit stale until confirmed by a node):
+    networks.restore().await;
+
+    // If there is a configuration file, reload it whenever it changes:
+    if let Some(path) = cli.config.clone() {
+        let networks = networks.clone();
+        tokio::spawn(async move {
+            if let Err(error) = config::watch(path, cli, networks).await {
+                error!(%error, "stopped watching configuration file");
+            }
+        });
+    }
+
+    // In background mode, update the metrics of each network continuously, independent of
+    // scrapes:
+    for app in networks.apps() {
+        if app.mode() == Mode::Background {
+            tokio::spawn(app.clone().poll());
+        }
+    }
+
+    // If there is an OTLP collector, push the metrics to it as well as serving them:
+    if let Some(otlp) = otlp {
+        tokio::spawn(otlp.push(networks.clone()));
+    }
+
+    // For hosts which Prometheus can't scrape, push the metrics out as well:
+    for pusher in pushers {
+        tokio::spawn(pusher.push(networks.clone()));
+    }
+
+    serve(server, networks).await
 }
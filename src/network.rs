@@ -0,0 +1,99 @@
+use prometheus::proto::MetricFamily;
+use std::sync::Arc;
+use tokio::task::JoinSet;
+
+use crate::{report, App, Options};
+
+/// Every network monitored, each with its own validators and nodes, and updated independently.
+///
+/// When a single network is monitored, it is unnamed, and its metrics carry no `network` label.
+#[derive(Debug, Clone)]
+pub struct Networks {
+    apps: Arc<Vec<App>>,
+}
+
+impl Networks {
+    /// Monitor the given networks.
+    pub fn new(apps: Vec<App>) -> Self {
+        Self {
+            apps: Arc::new(apps),
+        }
+    }
+
+    /// Get the application monitoring each network.
+    pub fn apps(&self) -> &[App] {
+        &self.apps
+    }
+
+    /// Get the prefix of the name of every metric, which is the same for every network.
+    pub fn namespace(&self) -> String {
+        self.apps.first().map(App::namespace).unwrap_or_default()
+    }
+
+    /// Replace the validators and nodes of each network with those in the given options.
+    ///
+    /// Networks can't be added or removed without restarting, so such changes are only logged.
+    pub fn reconfigure(&self, options: Options) {
+        let mut configured = if options.networks.is_empty() {
+            vec![options]
+        } else {
+            options.networks
+        };
+
+        for app in self.apps.iter() {
+            match configured
+                .iter()
+                .position(|options| options.network.as_deref() == app.network())
+            {
+                Some(index) => app.reconfigure(configured.remove(index)),
+                None => {
+                    warn!(network = ?app.network(), "removing a network requires a restart, keeping its previous configuration")
+                }
+            }
+        }
+        for options in configured {
+            warn!(network = ?options.network, "adding a network requires a restart");
+        }
+    }
+
+    /// Restore the info for each network from its state file, if it has one.
+    pub async fn restore(&self) {
+        for app in self.apps.iter() {
+            app.restore().await;
+        }
+    }
+
+    /// Prepare the metrics of every network for a scrape, concurrently.
+    pub async fn scrape(&self) {
+        let mut tasks = JoinSet::new();
+        for app in self.apps.iter().cloned() {
+            tasks.spawn(async move { app.scrape().await });
+        }
+        while tasks.join_next().await.is_some() {}
+    }
+
+    /// Check whether every network is ready to serve meaningful data, returning the reasons any is
+    /// not if it isn't.
+    pub fn readiness(&self) -> Result<(), Vec<String>> {
+        let mut reasons = Vec::new();
+        for app in self.apps.iter() {
+            if let Err(not_ready) = app.readiness() {
+                reasons.extend(not_ready.into_iter().map(|reason| match app.network() {
+                    Some(network) => format!("{network}: {reason}"),
+                    None => reason,
+                }));
+            }
+        }
+
+        if reasons.is_empty() {
+            Ok(())
+        } else {
+            Err(reasons)
+        }
+    }
+
+    /// Gather the metrics of every network, each labeled with its network if there are several.
+    pub fn gather(&self) -> Vec<MetricFamily> {
+        report::merge(self.apps.iter().map(App::gather))
+    }
+}
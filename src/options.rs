@@ -1,45 +1,178 @@
-use clap::Parser;
-use penumbra_stake::IdentityKey;
-use std::net::SocketAddr;
+use clap::{Parser, Subcommand};
+use reqwest::Url;
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    path::PathBuf,
+};
 use tonic::transport::Uri;
 
-use crate::{App, Client, Latest};
+use crate::{
+    alert::{default_rules, Alerting, Rule},
+    app::{Mode, Targets},
+    auth::{Auth, Credentials},
+    check::Check,
+    config::Config,
+    discover::Discovery,
+    latest::Spec,
+    network::Networks,
+    otlp::{Otlp, Protocol},
+    push::{Pusher, Target},
+    report::{validate_namespace, Label},
+    serve::{Https, Server},
+    status::Status,
+    tls::Tls,
+    App, Client, Latest,
+};
 
 /// Umbrella: a Prometheus exporter to monitor on-chain uptime for one or several Penumbra
 /// validators.
 ///
-/// Umbrella connects on-demand to one or more Penumbra RPC endpoints, picking uptime data from the
-/// node with the highest height. These metrics are served on a local HTTP server at the /metrics
-/// endpoint, which can be scraped by Prometheus. In other words, Umbrella is a caching proxy
-/// translating Prometheus scraping requests into RPC requests to Penumbra fullnodes, and
-/// translating their responses into Prometheus metrics.
+/// Umbrella connects on-demand (or periodically, in background mode) to one or more Penumbra RPC
+/// endpoints, picking uptime data from the node with the highest height. These metrics are served
+/// on a local HTTP server at the /metrics endpoint, which can be scraped by Prometheus. In other
+/// words, Umbrella is a caching proxy translating Prometheus scraping requests into RPC requests to
+/// Penumbra fullnodes, and translating their responses into Prometheus metrics.
 ///
 /// Please be nice to public RPC endpoints: if you're connecting to a public RPC, set it as a
 /// fallback node so that you only use its resources if your own fullnodes are all unreachable.
+#[derive(Parser, Debug)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+    /// The options with which to serve metrics, if no subcommand is given.
+    #[clap(flatten)]
+    pub options: Options,
+}
+
+/// What to do, if not the default of serving metrics.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Serve metrics (the default if no subcommand is given).
+    Serve(Options),
+    /// Check the uptime of validators once, as a Nagios or Icinga plugin, exiting with 0 (OK), 1
+    /// (WARNING), 2 (CRITICAL) or 3 (UNKNOWN).
+    Check(Check),
+    /// Print the current status of each validator as a table, asking the nodes once.
+    Status(Status),
+}
+
+impl Cli {
+    /// Get the command to run, defaulting to serving metrics with the top-level options.
+    pub fn command(self) -> Command {
+        self.command.unwrap_or(Command::Serve(self.options))
+    }
+}
+
+/// The options for monitoring validators, shared by every command.
 #[derive(Parser, Clone, Debug)]
 pub struct Options {
     /// Validator identity key to monitor for uptime (can be specified multiple times).
-    #[clap(short = 'v', long, required = true)]
-    pub validator: Vec<IdentityKey>,
+    ///
+    /// The identity key may be followed by `=` and an alias, like `penumbravalid1...=my-validator`,
+    /// which is attached to the validator's metrics as the `alias` label of `validator_info`.
+    #[clap(
+        short = 'v',
+        long,
+        value_name = "IDENTITY[=ALIAS]",
+        required_unless_present_any(["config", "all_validators"])
+    )]
+    pub validator: Vec<Spec>,
     /// Fullnode RPC endpoint to monitor for health and use as a primary source for validator uptime
     /// information (can be specified multiple times).
-    #[clap(short = 'n', long, required_unless_present("fallback"))]
+    #[clap(short = 'n', long, required_unless_present_any(["fallback", "config"]))]
     pub node: Vec<Uri>,
     /// Fullnode RPC endpoint to use as a backup source for validator uptime information (can be
     /// specified multiple times).
     ///
     /// If all of the primary nodes are unavailable, the client will attempt to connect to the
     /// fallback nodes one at a time in the order they are specified.
-    #[clap(short = 'f', long, required_unless_present("node"))]
+    #[clap(short = 'f', long, required_unless_present_any(["node", "config"]))]
     pub fallback: Vec<Uri>,
+    /// Monitor every validator on the chain, in addition to any specified with `--validator`.
+    ///
+    /// The validator set is re-read on every update, so validators are added as they join the
+    /// chain and removed as they leave it.
+    #[clap(short = 'a', long)]
+    pub all_validators: bool,
+    /// When monitoring every validator, only monitor those in the active set.
+    #[clap(long, requires = "all_validators")]
+    pub active_only: bool,
+    /// Webhook URL to notify with a JSON payload when an alert fires or resolves (can be specified
+    /// multiple times).
+    ///
+    /// Alerting rules are set in the configuration file; if none are set, the default rules follow
+    /// the recommendations for an active validator in the README.
+    #[clap(short = 'w', long)]
+    pub webhook: Vec<Url>,
+    /// Alerting rules, which can only be set in the configuration file.
+    #[clap(skip)]
+    pub alerts: Vec<Rule>,
+    /// Custom TLS settings for particular nodes or fallbacks, which can only be set in the
+    /// configuration file.
+    #[clap(skip)]
+    pub tls: HashMap<Uri, Tls>,
+    /// Configuration file (TOML) with further options.
+    ///
+    /// The file may contain the keys `validators`, `nodes`, `fallbacks`, `webhooks`, `alerts`
+    /// (lists, appended to those given on the command line), and `all-validators`, `active-only`,
+    /// `bind`, `mode`, `poll-interval`, `connect-timeout`, `max-staleness`, `halt-threshold`,
+    /// `state-file`, `namespace`, `tls-cert`, `tls-key`, `bearer-token`, `basic-auth`,
+    /// `otlp-endpoint`, `otlp-protocol`, `otlp-interval`, `pushgateway`, `pushgateway-job`,
+    /// `remote-write`, `push-interval`, `push-basic-auth` (which override the command line), and
+    /// `labels` (a table of constant labels, merged with those given on the command line). It may
+    /// also contain a `networks` table of named networks to monitor, each with its own
+    /// `validators`, `nodes`, `fallbacks`, `all-validators`, `active-only` and `state-file`. Nodes
+    /// and fallbacks in the file may be given custom TLS settings. The file is reloaded when it
+    /// changes or on SIGHUP, without restarting the server; changes to `bind`, `mode`, `tls-cert`,
+    /// `tls-key`, `bearer-token`, `basic-auth` and the `otlp-` and push options only take effect on
+    /// restart.
+    #[clap(short = 'c', long)]
+    pub config: Option<PathBuf>,
     /// Port on which to serve Prometheus metrics.
     #[clap(short = 'b', long, default_value = "127.0.0.1:1984")]
     pub bind: SocketAddr,
+    /// PEM certificate file with which to serve HTTPS instead of plain HTTP (requires `--tls-key`).
+    ///
+    /// The certificate and key are reloaded on SIGHUP, so that renewed certificates are served
+    /// without restarting.
+    #[clap(long)]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key file of the certificate given with `--tls-cert`.
+    #[clap(long)]
+    pub tls_key: Option<PathBuf>,
+    /// Bearer token required to access metrics and the JSON API.
+    ///
+    /// The health probes at /healthz and /readyz never require credentials. If basic
+    /// authentication is also set, either is accepted.
+    #[clap(long, env = "UMBRELLA_BEARER_TOKEN", hide_env_values = true)]
+    pub bearer_token: Option<String>,
+    /// User name and password required to access metrics and the JSON API, with HTTP basic
+    /// authentication.
+    ///
+    /// The health probes at /healthz and /readyz never require credentials. If a bearer token is
+    /// also set, either is accepted.
+    #[clap(
+        long,
+        value_name = "USER:PASSWORD",
+        env = "UMBRELLA_BASIC_AUTH",
+        hide_env_values = true
+    )]
+    pub basic_auth: Option<Credentials>,
+    /// When to update the metrics from the fullnodes.
+    ///
+    /// In on-demand mode, fullnodes are only queried when Prometheus scrapes the metrics endpoint.
+    /// In background mode, fullnodes are queried continuously at the polling interval, and scrapes
+    /// are answered immediately from the cached result, so that data is collected even when
+    /// Prometheus is down or slow.
+    #[clap(short = 'm', long, value_enum, default_value_t)]
+    pub mode: Mode,
     /// Minimum polling interval for updating the metrics.
     ///
-    /// Metrics are updated from fullnodes only on-demand, but the result is cached for this
-    /// duration to avoid excessive load on the fullnodes. This option does not usually need to be
-    /// altered.
+    /// In on-demand mode, the result of an update is cached for this duration to avoid excessive
+    /// load on the fullnodes. In background mode, this is the interval between updates, and you may
+    /// want to increase it. This option does not usually need to be altered.
     #[clap(short = 'p', long, default_value = "1s")]
     pub poll_interval: humantime::Duration,
     /// Timeout for connecting to each fullnode.
@@ -48,34 +181,286 @@ pub struct Options {
     /// aborted and considered failed. This option does not usually need to be altered.
     #[clap(short = 't', long, default_value = "5s")]
     pub connect_timeout: humantime::Duration,
+    /// Maximum age of each validator's info for umbrella to report itself ready at /readyz.
+    ///
+    /// In on-demand mode, info is only updated when metrics are scraped, so this should be longer
+    /// than the scrape interval.
+    #[clap(long, default_value = "5m")]
+    pub max_staleness: humantime::Duration,
+    /// How long the chain's height must stop advancing across all nodes before the chain is
+    /// considered halted.
+    ///
+    /// This should be comfortably longer than the usual block time, plus the polling or scrape
+    /// interval.
+    #[clap(long, default_value = "1m")]
+    pub halt_threshold: humantime::Duration,
+    /// File in which to save the latest info about each validator after every update, to be
+    /// restored on the next start.
+    ///
+    /// Restored info is served immediately on start, but is considered stale until it is confirmed
+    /// by a node.
+    #[clap(short = 's', long)]
+    pub state_file: Option<PathBuf>,
+    /// Prefix for the name of every metric, like `penumbra_validator_`, to avoid collisions with
+    /// other exporters in a shared Prometheus.
+    #[clap(long, default_value = "")]
+    pub namespace: String,
+    /// Constant label to attach to every metric, like `environment=mainnet` (can be specified
+    /// multiple times).
+    ///
+    /// The `chain_id` label is attached automatically once a node reports the chain ID; set it
+    /// explicitly to override it, or to an empty value to leave it out.
+    #[clap(short = 'l', long, value_name = "NAME=VALUE")]
+    pub label: Vec<Label>,
+    /// OpenTelemetry collector to push metrics to over OTLP, like `http://localhost:4317`, in
+    /// addition to serving them for scraping.
+    ///
+    /// Over HTTP, metrics are posted to `/v1/metrics` unless the URL has a path of its own.
+    #[clap(long, value_name = "URL")]
+    pub otlp_endpoint: Option<Url>,
+    /// Protocol with which to push metrics to the OTLP collector.
+    #[clap(long, value_enum, default_value_t)]
+    pub otlp_protocol: Protocol,
+    /// Interval between pushes of metrics to the OTLP collector.
+    ///
+    /// Each push updates the metrics as a scrape would, so in on-demand mode, this is also how
+    /// often the fullnodes are queried if nothing scrapes the metrics.
+    #[clap(long, default_value = "1m")]
+    pub otlp_interval: humantime::Duration,
+    /// Pushgateway to push metrics to, like `http://pushgateway:9091`, for hosts which Prometheus
+    /// can't scrape.
+    ///
+    /// Metrics are pushed under the job given with `--pushgateway-job`, replacing those pushed
+    /// before.
+    #[clap(long, value_name = "URL")]
+    pub pushgateway: Option<Url>,
+    /// Job name under which to push metrics to the Pushgateway.
+    #[clap(long, default_value = "umbrella")]
+    pub pushgateway_job: String,
+    /// Prometheus remote-write endpoint to push metrics to, like
+    /// `http://prometheus:9090/api/v1/write`, for hosts which Prometheus can't scrape.
+    #[clap(long, value_name = "URL")]
+    pub remote_write: Option<Url>,
+    /// Interval between pushes to the Pushgateway or remote-write endpoint.
+    ///
+    /// A failed push is retried with exponential backoff until the next push is due.
+    #[clap(long, default_value = "1m")]
+    pub push_interval: humantime::Duration,
+    /// User name and password with which to authenticate to the Pushgateway or remote-write
+    /// endpoint, with HTTP basic authentication.
+    #[clap(
+        long,
+        value_name = "USER:PASSWORD",
+        env = "UMBRELLA_PUSH_BASIC_AUTH",
+        hide_env_values = true
+    )]
+    pub push_basic_auth: Option<Credentials>,
+    /// The name of the network these options are for, if it is one of several.
+    #[clap(skip)]
+    pub network: Option<String>,
+    /// The options for each of several named networks, which can only be set in the configuration
+    /// file.
+    #[clap(skip)]
+    pub networks: Vec<Options>,
 }
 
 impl Options {
-    /// Convert the options into an application which can be run.
+    /// Merge in the configuration file, if one was specified, and check that the resulting options
+    /// are complete.
+    pub async fn load(self) -> eyre::Result<Self> {
+        let options = match self.config.clone() {
+            Some(path) => Config::read(&path)
+                .await
+                .map_err(|error| eyre!("failed to read {}: {error}", path.display()))?
+                .merge_into(self)?,
+            None => self,
+        };
+
+        validate_namespace(&options.namespace)?;
+        if options.networks.is_empty() {
+            options.check()?;
+        } else {
+            // Validators and nodes can't be shared between networks, so they must all be given per
+            // network:
+            ensure!(
+                options.validator.is_empty()
+                    && options.node.is_empty()
+                    && options.fallback.is_empty()
+                    && !options.all_validators,
+                "validators and nodes must be given for each network when `networks` are configured"
+            );
+            for network in options.networks.iter() {
+                network.check().map_err(|error| {
+                    eyre!(
+                        "in network `{}`: {error}",
+                        network.network.as_deref().unwrap_or_default()
+                    )
+                })?;
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Check that the options specify something to monitor and somewhere to monitor it from.
+    fn check(&self) -> eyre::Result<()> {
+        ensure!(
+            !self.validator.is_empty() || self.all_validators,
+            "no validators specified to monitor"
+        );
+        ensure!(
+            self.all_validators || !self.active_only,
+            "`active-only` requires `all-validators`"
+        );
+        ensure!(
+            !self.all_validators || !self.node.is_empty(),
+            "`all-validators` requires a primary node, since fallbacks are never asked for the \
+             validator set"
+        );
+        ensure!(
+            !self.node.is_empty() || !self.fallback.is_empty(),
+            "no nodes or fallbacks specified to connect to"
+        );
+        // Alerts are tracked by the name of their rule, so rules with the same name would clash:
+        let mut names = HashSet::new();
+        for rule in self.alerts.iter() {
+            ensure!(
+                names.insert(&rule.name),
+                "duplicate alerting rule name `{}`",
+                rule.name
+            );
+        }
+        Ok(())
+    }
+
+    /// Get the settings for serving metrics, which only take effect on start.
+    pub fn server(&self) -> eyre::Result<Server> {
+        let https = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Some(Https {
+                cert: cert.clone(),
+                key: key.clone(),
+            }),
+            (None, None) => None,
+            _ => bail!("a TLS certificate and its key must be given together"),
+        };
+        if https.is_none() && (self.bearer_token.is_some() || self.basic_auth.is_some()) {
+            warn!(
+                "credentials are required but will be sent in the clear, since TLS is not enabled"
+            );
+        }
+        Ok(Server {
+            bind: self.bind,
+            https,
+            auth: Auth::new(self.bearer_token.clone(), self.basic_auth.clone()),
+        })
+    }
+
+    /// Get the exporter pushing metrics to an OTLP collector, if one is configured, which only
+    /// takes effect on start.
+    pub fn otlp(&self) -> eyre::Result<Option<Otlp>> {
+        self.otlp_endpoint
+            .clone()
+            .map(|endpoint| Otlp::new(endpoint, self.otlp_protocol, self.otlp_interval.into()))
+            .transpose()
+    }
+
+    /// Get the pushers sending metrics to a Pushgateway or remote-write endpoint, if any are
+    /// configured, which only take effect on start.
+    pub fn pushers(&self) -> eyre::Result<Vec<Pusher>> {
+        let pushgateway = self.pushgateway.clone().map(|url| Target::Pushgateway {
+            url,
+            job: self.pushgateway_job.clone(),
+        });
+        let remote_write = self.remote_write.clone().map(Target::RemoteWrite);
+        pushgateway
+            .into_iter()
+            .chain(remote_write)
+            .map(|target| {
+                Pusher::new(
+                    target,
+                    self.push_interval.into(),
+                    self.push_basic_auth.clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Convert the options into an application monitoring every network which can be run.
+    pub fn into_networks(self) -> Networks {
+        if self.networks.is_empty() {
+            Networks::new(vec![self.into_app()])
+        } else {
+            Networks::new(self.networks.into_iter().map(Options::into_app).collect())
+        }
+    }
+
+    /// Convert the options into an application monitoring a single network.
     pub fn into_app(self) -> App {
+        App::new(self.mode, self.network.clone(), self.into_targets())
+    }
+
+    /// Convert the options into the reconfigurable part of an application.
+    pub fn into_targets(self) -> Targets {
         // List of sets of clients to try to connect to -- first, try all the primary nodes
         // concurrently, then try each fallback node in order:
+        let tls = self.tls;
+        let client = |uri: Uri| match tls.get(&uri) {
+            Some(tls) => Client::new(uri).with_tls(tls.clone()),
+            None => Client::new(uri),
+        };
         let mut node_sets = Vec::with_capacity(1 + self.fallback.len());
-        node_sets.push(self.node.into_iter().map(Client::new).collect::<Vec<_>>());
+        node_sets.push(self.node.into_iter().map(client).collect::<Vec<_>>());
         node_sets.extend(
             self.fallback
                 .into_iter()
-                .map(Client::new)
+                .map(client)
                 .map(|fallback| vec![fallback]),
         );
 
         // Make an updateable info cell for each validator:
+        let explicit = self.validator.iter().map(|spec| spec.identity).collect();
         let info = self
             .validator
             .into_iter()
             .map(Latest::new)
             .collect::<Vec<_>>();
 
-        App::new(
+        // Further validators may be discovered from the chain:
+        let discovery = match (self.all_validators, self.active_only) {
+            (false, _) => Discovery::Off,
+            (true, false) => Discovery::All,
+            (true, true) => Discovery::ActiveOnly,
+        };
+
+        // Alert using the default rules unless some are configured:
+        let alerting = Alerting {
+            rules: if self.alerts.is_empty() {
+                default_rules()
+            } else {
+                self.alerts
+            },
+            webhooks: self.webhook,
+            network: self.network,
+        };
+
+        Targets {
             node_sets,
             info,
-            self.poll_interval.into(),
-            self.connect_timeout.into(),
-        )
+            explicit,
+            discovery,
+            alerting,
+            poll_interval: self.poll_interval.into(),
+            connect_timeout: self.connect_timeout.into(),
+            max_staleness: self.max_staleness.into(),
+            halt_threshold: self.halt_threshold.into(),
+            state_file: self.state_file,
+            namespace: self.namespace,
+            // Later labels override earlier ones of the same name:
+            labels: self
+                .label
+                .into_iter()
+                .map(|label| (label.name, label.value))
+                .collect(),
+        }
     }
 }
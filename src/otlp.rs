@@ -0,0 +1,265 @@
+use clap::ValueEnum;
+use opentelemetry_proto::tonic::{
+    collector::metrics::v1::{ExportMetricsServiceRequest, ExportMetricsServiceResponse},
+    common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue},
+    metrics::v1::{
+        metric::Data, number_data_point, AggregationTemporality, Gauge, Metric, NumberDataPoint,
+        ResourceMetrics, ScopeMetrics, Sum,
+    },
+    resource::v1::Resource,
+};
+use prometheus::proto::{self, MetricFamily, MetricType};
+use prost::Message;
+use reqwest::{header::CONTENT_TYPE, Url};
+use serde::Deserialize;
+use std::{
+    convert::Infallible,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::{interval, MissedTickBehavior};
+use tonic::{
+    client::Grpc,
+    codec::ProstCodec,
+    codegen::http::uri::PathAndQuery,
+    transport::{Channel, Endpoint, Uri},
+};
+
+use crate::network::Networks;
+
+/// The path of the export method of the OTLP metrics service, over gRPC.
+const GRPC_EXPORT: &str = "/opentelemetry.proto.collector.metrics.v1.MetricsService/Export";
+
+/// The path to which metrics are posted over HTTP, if the endpoint has no path of its own.
+const HTTP_PATH: &str = "/v1/metrics";
+
+/// The protocol with which to push metrics to an OTLP collector.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Protocol {
+    /// OTLP over gRPC, usually on port 4317.
+    #[default]
+    Grpc,
+    /// OTLP over HTTP with protobuf payloads, usually on port 4318.
+    Http,
+}
+
+/// An exporter pushing metrics to an OpenTelemetry collector over OTLP, for setups without a
+/// Prometheus scraper.
+#[derive(Debug, Clone)]
+pub struct Otlp {
+    /// The collector's endpoint.
+    endpoint: Url,
+    /// The transport to the collector.
+    transport: Transport,
+    /// How often to push metrics.
+    interval: Duration,
+    /// When the exporter started, which is the start of every cumulative counter.
+    start: SystemTime,
+}
+
+/// The connection to a collector, which is reused across pushes.
+#[derive(Debug, Clone)]
+enum Transport {
+    Grpc(Channel),
+    Http(reqwest::Client),
+}
+
+impl Otlp {
+    /// Make an exporter to the collector at the given endpoint.
+    ///
+    /// Over gRPC, the connection is made lazily and re-established as needed, so the collector
+    /// doesn't need to be up yet. Either way, a push which takes longer than the interval is given
+    /// up on, since the next push supersedes it.
+    pub fn new(endpoint: Url, protocol: Protocol, interval: Duration) -> eyre::Result<Self> {
+        let transport = match protocol {
+            Protocol::Grpc => {
+                let uri: Uri = endpoint.as_str().parse()?;
+                Transport::Grpc(
+                    Endpoint::from(uri)
+                        .connect_timeout(interval)
+                        .timeout(interval)
+                        .connect_lazy(),
+                )
+            }
+            Protocol::Http => {
+                Transport::Http(reqwest::Client::builder().timeout(interval).build()?)
+            }
+        };
+        Ok(Self {
+            endpoint,
+            transport,
+            interval,
+            start: SystemTime::now(),
+        })
+    }
+
+    /// Push the metrics of every network to the collector forever, at the exporter's interval.
+    ///
+    /// Metrics are updated before each push as they would be before a scrape, so this works in
+    /// either mode. A failed push is logged and not retried, since the next push supersedes it.
+    pub async fn push(self, networks: Networks) -> Infallible {
+        let mut ticks = interval(self.interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            networks.scrape().await;
+            if let Err(error) = self.export(&networks.gather()).await {
+                warn!(endpoint = %self.endpoint, %error, "failed to push metrics over OTLP");
+            }
+        }
+    }
+
+    /// Push the given metrics to the collector once.
+    pub async fn export(&self, families: &[MetricFamily]) -> eyre::Result<()> {
+        let request = convert(families, self.start, SystemTime::now());
+        let response = match &self.transport {
+            Transport::Grpc(channel) => {
+                let mut grpc = Grpc::new(channel.clone());
+                grpc.ready().await?;
+                grpc.unary(
+                    tonic::Request::new(request),
+                    PathAndQuery::from_static(GRPC_EXPORT),
+                    ProstCodec::default(),
+                )
+                .await?
+                .into_inner()
+            }
+            Transport::Http(http) => {
+                let mut url = self.endpoint.clone();
+                if url.path() == "/" {
+                    url.set_path(HTTP_PATH);
+                }
+                let body = http
+                    .post(url)
+                    .header(CONTENT_TYPE, "application/x-protobuf")
+                    .body(request.encode_to_vec())
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?;
+                ExportMetricsServiceResponse::decode(body)?
+            }
+        };
+
+        // The collector may accept some data points but not others:
+        if let Some(partial) = response.partial_success {
+            if partial.rejected_data_points > 0 || !partial.error_message.is_empty() {
+                warn!(
+                    endpoint = %self.endpoint,
+                    rejected = partial.rejected_data_points,
+                    message = %partial.error_message,
+                    "collector rejected some metrics"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Convert gathered Prometheus metrics into an OTLP export request.
+///
+/// Gauges become OTLP gauges, and counters become cumulative monotonic sums starting at `start`;
+/// labels become attributes. Nothing in umbrella is a histogram or summary, so those are skipped.
+pub fn convert(
+    families: &[MetricFamily],
+    start: SystemTime,
+    now: SystemTime,
+) -> ExportMetricsServiceRequest {
+    let (start, now) = (unix_nanos(start), unix_nanos(now));
+    let metrics = families
+        .iter()
+        .filter_map(|family| {
+            let name = family.get_name();
+            let points = |value: fn(&proto::Metric) -> f64| -> Vec<NumberDataPoint> {
+                family
+                    .get_metric()
+                    .iter()
+                    .map(|metric| NumberDataPoint {
+                        attributes: metric
+                            .get_label()
+                            .iter()
+                            .map(|label| attribute(label.get_name(), label.get_value()))
+                            .collect(),
+                        start_time_unix_nano: start,
+                        time_unix_nano: now,
+                        value: Some(number_data_point::Value::AsDouble(value(metric))),
+                        ..Default::default()
+                    })
+                    .collect()
+            };
+            let (name, data) = match family.get_field_type() {
+                MetricType::GAUGE => (
+                    name,
+                    Data::Gauge(Gauge {
+                        data_points: points(|metric| metric.get_gauge().get_value()),
+                    }),
+                ),
+                MetricType::UNTYPED => (
+                    name,
+                    Data::Gauge(Gauge {
+                        data_points: points(|metric| metric.get_untyped().get_value()),
+                    }),
+                ),
+                // OTLP names counters without the Prometheus `_total` suffix:
+                MetricType::COUNTER => (
+                    name.strip_suffix("_total").unwrap_or(name),
+                    Data::Sum(Sum {
+                        data_points: points(|metric| metric.get_counter().get_value()),
+                        aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                        is_monotonic: true,
+                    }),
+                ),
+                MetricType::HISTOGRAM | MetricType::SUMMARY => {
+                    warn!(%name, "cannot push histograms or summaries over OTLP");
+                    return None;
+                }
+            };
+            Some(Metric {
+                name: name.to_string(),
+                description: family.get_help().to_string(),
+                unit: if name.ends_with("_seconds") { "s" } else { "" }.to_string(),
+                data: Some(data),
+            })
+        })
+        .collect();
+
+    ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Some(Resource {
+                attributes: vec![
+                    attribute("service.name", env!("CARGO_PKG_NAME")),
+                    attribute("service.version", env!("CARGO_PKG_VERSION")),
+                ],
+                dropped_attributes_count: 0,
+            }),
+            scope_metrics: vec![ScopeMetrics {
+                scope: Some(InstrumentationScope {
+                    name: env!("CARGO_PKG_NAME").to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    ..Default::default()
+                }),
+                metrics,
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    }
+}
+
+/// Make a string attribute.
+fn attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_string())),
+        }),
+    }
+}
+
+/// Convert a time into nanoseconds since the Unix epoch, as OTLP expects.
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
@@ -0,0 +1,303 @@
+use eyre::Ok;
+use parking_lot::Mutex;
+use penumbra_proto::core::component::{
+    sct::v1::{self as sct, EpochByHeightRequest},
+    stake::v1::{Penalty, ValidatorPenaltyRequest},
+};
+use penumbra_stake::{validator, IdentityKey};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::Duration};
+use tokio::{task::JoinSet, time::timeout};
+
+use crate::{Client, Latest};
+
+/// The slashing penalties applied to a validator, tracked across epochs.
+///
+/// Penalties are fractions of the delegation pool slashed, in the range [0, 1].
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Penalties {
+    /// The most recent past epoch whose penalty has been checked, after which it can no longer
+    /// change, or `None` if no past epoch has been checked.
+    settled_through: Option<u64>,
+    /// The compounded penalty over all settled epochs.
+    settled: f64,
+    /// The current epoch, as of the last check.
+    current_epoch: Option<u64>,
+    /// The penalty applied in each epoch in which a non-zero penalty was observed.
+    applied: BTreeMap<u64, f64>,
+    /// The state of the validator as of the last check, used to detect jailing and tombstoning.
+    state: Option<validator::State>,
+}
+
+impl Penalties {
+    /// Get the penalty applied in each epoch in which a non-zero penalty was observed.
+    ///
+    /// Epochs before monitoring started, or which ended while umbrella was stopped, are only
+    /// included in [`Penalties::cumulative`].
+    pub fn applied(&self) -> &BTreeMap<u64, f64> {
+        &self.applied
+    }
+
+    /// Get the compounded penalty over all epochs up to and including the current one, or `None`
+    /// if penalties have never been checked.
+    pub fn cumulative(&self) -> Option<f64> {
+        let current_epoch = self.current_epoch?;
+        let current = self.applied.get(&current_epoch).copied().unwrap_or(0.0);
+        Some(compound(self.settled, current))
+    }
+}
+
+/// Compound two penalties, as if one was applied after the other.
+fn compound(a: f64, b: f64) -> f64 {
+    1.0 - (1.0 - a) * (1.0 - b)
+}
+
+/// Parse a penalty from its protobuf representation: a 128.128-bit fixed-point number, as 32
+/// big-endian bytes.
+fn parse_penalty(penalty: Penalty) -> eyre::Result<f64> {
+    let bytes: [u8; 32] = penalty
+        .inner
+        .try_into()
+        .map_err(|_| eyre!("invalid penalty data"))?;
+    let (integer, fraction) = bytes.split_at(16);
+    let integer = u128::from_be_bytes(integer.try_into().expect("split in half"));
+    let fraction = u128::from_be_bytes(fraction.try_into().expect("split in half"));
+    Ok(integer as f64 + fraction as f64 / 2f64.powi(128))
+}
+
+/// An epoch of the chain as last reported by a node, from which the epoch of later heights is
+/// derived without asking again until the epoch ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Epoch {
+    /// The index of the epoch.
+    index: u64,
+    /// The height of the first block of the epoch.
+    start_height: u64,
+    /// The number of blocks in an epoch, once known from the start of the epoch before.
+    duration: Option<u64>,
+}
+
+impl Epoch {
+    /// Get the index of this epoch if it contains the given height, or `None` if it doesn't or its
+    /// end isn't known.
+    fn index_at(&self, height: u64) -> Option<u64> {
+        let end = self.start_height + self.duration?;
+        (self.start_height..end)
+            .contains(&height)
+            .then_some(self.index)
+    }
+}
+
+/// Check the slashing penalties of each validator as of the given height, asking each node in each
+/// set of nodes in turn until every validator's penalties have been checked.
+///
+/// The epoch containing the height is only asked for once the last known epoch has ended. Not every
+/// node serves epochs and penalties, so failing to get them is logged, but doesn't count against
+/// the node's health or connection.
+pub async fn update_penalties(
+    node_sets: &[Vec<Client>],
+    info: &[Latest],
+    height: u64,
+    connect_timeout: Duration,
+    last_epoch: &Mutex<Option<Epoch>>,
+) {
+    let mut unchecked = info.to_vec();
+
+    for client in node_sets.iter().flatten() {
+        if unchecked.is_empty() {
+            break;
+        }
+
+        if let Err(error) = client.connect(connect_timeout).await {
+            warn!(node = %client.uri(), "{}", error);
+            continue;
+        }
+
+        let previous = *last_epoch.lock();
+        let epoch = match previous.and_then(|epoch| epoch.index_at(height)) {
+            Some(epoch) => epoch,
+            None => match timeout(connect_timeout, current_epoch(client, height, previous)).await {
+                Result::Ok(Result::Ok(epoch)) => {
+                    *last_epoch.lock() = Some(epoch);
+                    epoch.index
+                }
+                Result::Ok(Err(error)) => {
+                    warn!(node = %client.uri(), "failed to get current epoch: {}", error);
+                    continue;
+                }
+                Err(elapsed) => {
+                    warn!(node = %client.uri(), "failed to get current epoch: {}", elapsed);
+                    continue;
+                }
+            },
+        };
+
+        // Check the penalties for every remaining validator concurrently:
+        let mut tasks = JoinSet::new();
+        for latest in unchecked.iter().cloned() {
+            let client = client.clone();
+            tasks.spawn(async move {
+                let checked = timeout(
+                    connect_timeout,
+                    update_validator_penalties(&client, &latest, epoch),
+                )
+                .await
+                .map_err(eyre::Report::from)
+                .and_then(|result| result);
+                if let Err(error) = &checked {
+                    warn!(node = %client.uri(), validator = %latest.identity(), "failed to get penalties: {}", error);
+                }
+                (latest, checked.is_ok())
+            });
+        }
+
+        unchecked.clear();
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Result::Ok((latest, false)) => unchecked.push(latest),
+                Result::Ok((_, true)) => {}
+                Err(error) => error!(node = %client.uri(), %error, "failed to check penalties"),
+            }
+        }
+    }
+
+    if !unchecked.is_empty() {
+        let validators = unchecked.iter().map(Latest::identity).collect::<Vec<_>>();
+        error!(?validators, "failed to get penalties from any data source");
+    }
+}
+
+/// Get the epoch containing the given height from a single node, along with the duration of
+/// epochs: from the previously reported epoch if it is the one just before, or else by asking for
+/// the epoch before too.
+async fn current_epoch(
+    client: &Client,
+    height: u64,
+    previous: Option<Epoch>,
+) -> eyre::Result<Epoch> {
+    let epoch = epoch_by_height(client, height).await?;
+    let duration = match previous {
+        Some(previous) if previous.index + 1 == epoch.index => {
+            Some(epoch.start_height - previous.start_height)
+        }
+        _ if epoch.start_height > 0 => {
+            let before = epoch_by_height(client, epoch.start_height - 1).await?;
+            Some(epoch.start_height - before.start_height)
+        }
+        _ => None,
+    };
+    Ok(Epoch {
+        index: epoch.index,
+        start_height: epoch.start_height,
+        duration,
+    })
+}
+
+/// Get the epoch containing the given height from a single node.
+async fn epoch_by_height(client: &Client, height: u64) -> eyre::Result<sct::Epoch> {
+    let mut sct_client = client
+        .sct()
+        .ok_or_else(|| eyre!("client is not connected"))?;
+
+    Ok(sct_client
+        .epoch_by_height(EpochByHeightRequest { height })
+        .await?
+        .into_inner()
+        .epoch
+        .ok_or_else(|| eyre!("no epoch data"))?)
+}
+
+/// Get the compounded penalty of a validator over the given range of epochs from a single node.
+async fn penalty(
+    client: &Client,
+    validator: IdentityKey,
+    start_epoch_index: u64,
+    end_epoch_index: u64,
+) -> eyre::Result<f64> {
+    let mut stake_client = client
+        .get()
+        .ok_or_else(|| eyre!("client is not connected"))?;
+
+    parse_penalty(
+        stake_client
+            .validator_penalty(ValidatorPenaltyRequest {
+                identity_key: Some(validator.into()),
+                start_epoch_index,
+                end_epoch_index,
+            })
+            .await?
+            .into_inner()
+            .penalty
+            .ok_or_else(|| eyre!("no penalty data"))?,
+    )
+}
+
+/// Check the penalties of a single validator up to the given current epoch from a single node,
+/// logging when the validator is jailed or tombstoned.
+///
+/// Penalties are only applied when a validator is jailed or tombstoned, and only settle when an
+/// epoch ends, so nothing is asked for unless the epoch or the validator's state changed since the
+/// last check.
+async fn update_validator_penalties(
+    client: &Client,
+    latest: &Latest,
+    epoch: u64,
+) -> eyre::Result<()> {
+    let validator = latest.identity();
+    let state = latest.state();
+    let mut penalties = latest.penalties();
+    if penalties.current_epoch == Some(epoch) && penalties.state == state {
+        return Ok(());
+    }
+    let before = penalties.cumulative().unwrap_or_default();
+
+    // Settle the epochs which have ended since the last check with a single query. Only a single
+    // ended epoch is attributed to itself: a longer gap, like all of history on the first check or
+    // the time spent stopped before a restore, is settled at once to avoid querying every epoch in
+    // it, and any penalty recorded for it while it was current is superseded:
+    let unsettled = penalties.settled_through.map_or(0, |settled| settled + 1);
+    if unsettled < epoch {
+        let past = penalty(client, validator, unsettled, epoch - 1).await?;
+        if penalties.settled_through.is_some() && unsettled + 1 == epoch && past > 0.0 {
+            penalties.applied.insert(unsettled, past);
+        } else {
+            penalties.applied.retain(|&applied, _| applied < unsettled);
+        }
+        penalties.settled = compound(penalties.settled, past);
+    }
+    penalties.settled_through = epoch.checked_sub(1);
+
+    // The penalty for the current epoch can still change until the epoch ends:
+    let current = penalty(client, validator, epoch, epoch).await?;
+    if current > 0.0 {
+        penalties.applied.insert(epoch, current);
+    } else {
+        penalties.applied.remove(&epoch);
+    }
+    penalties.current_epoch = Some(epoch);
+
+    // Log the transition into being jailed or tombstoned, along with how much was slashed since
+    // the last check and in total, since the slash may have been settled along with a past epoch:
+    if state != penalties.state {
+        if let Some(state @ (validator::State::Jailed | validator::State::Tombstoned)) = state {
+            let cumulative = penalties.cumulative().unwrap_or_default();
+            let slashed = if before < 1.0 {
+                1.0 - (1.0 - cumulative) / (1.0 - before)
+            } else {
+                0.0
+            };
+            warn!(
+                %validator,
+                %state,
+                epoch,
+                penalty = %format!("{:.4}%", slashed * 100.0),
+                cumulative_penalty = %format!("{:.4}%", cumulative * 100.0),
+                "validator was slashed"
+            );
+        }
+        penalties.state = state;
+    }
+
+    latest.set_penalties(penalties);
+    Ok(())
+}
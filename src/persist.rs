@@ -0,0 +1,81 @@
+use parking_lot::Mutex;
+use std::{io::ErrorKind, path::Path, time::Duration};
+use tokio::{fs, time::Instant};
+
+use crate::{latest::Saved, Latest};
+
+/// How often to save the state file even if the info in it is unchanged, so that the age of the
+/// info is still about right when it is restored.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The fields of each validator's saved info which are compared to tell whether it has changed: the
+/// ones worth restoring promptly. The others (uptime, rates, source and when it was updated) change
+/// on nearly every block, so changes to them are only saved every [`SAVE_INTERVAL`].
+const COMPARED: &[&str] = &["identity", "definition", "status", "penalties", "incidents"];
+
+/// The state file as it was last saved, for telling whether it needs to be saved again.
+#[derive(Debug, Clone)]
+pub struct LastSaved {
+    /// The fields of the saved info which are compared.
+    info: serde_json::Value,
+    /// When the file was saved.
+    at: Instant,
+}
+
+/// Save the lasting information about each validator to the state file, unless its definition,
+/// status, penalties and incidents are unchanged since it was last saved, less than a minute ago.
+///
+/// The file is replaced atomically, so a crash while saving never leaves a truncated file behind.
+pub async fn save(
+    path: &Path,
+    info: &[Latest],
+    last_saved: &Mutex<Option<LastSaved>>,
+) -> eyre::Result<()> {
+    let saved = info.iter().filter_map(Latest::save).collect::<Vec<_>>();
+    let mut unchanged = serde_json::to_value(&saved)?;
+    if let Some(validators) = unchanged.as_array_mut() {
+        for validator in validators
+            .iter_mut()
+            .filter_map(serde_json::Value::as_object_mut)
+        {
+            validator.retain(|field, _| COMPARED.contains(&field.as_str()));
+            // The incidents note the height of the latest uptime snapshot, which also changes on
+            // nearly every block:
+            if let Some(incidents) = validator
+                .get_mut("incidents")
+                .and_then(serde_json::Value::as_object_mut)
+            {
+                incidents.remove("as_of_height");
+            }
+        }
+    }
+    if last_saved
+        .lock()
+        .as_ref()
+        .is_some_and(|last| last.info == unchanged && last.at.elapsed() < SAVE_INTERVAL)
+    {
+        return Ok(());
+    }
+    let contents = serde_json::to_vec(&saved)?;
+
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, contents).await?;
+    fs::rename(&temporary, path).await?;
+    *last_saved.lock() = Some(LastSaved {
+        info: unchanged,
+        at: Instant::now(),
+    });
+    Ok(())
+}
+
+/// Load the lasting information about each validator from the state file.
+///
+/// A missing state file is not an error, since there is nothing to restore on the first start.
+pub async fn load(path: &Path) -> eyre::Result<Vec<Saved>> {
+    let contents = match fs::read(path).await {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+    Ok(serde_json::from_slice(&contents)?)
+}
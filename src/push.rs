@@ -0,0 +1,240 @@
+use prometheus::proto::{MetricFamily, MetricType};
+use prost::Message;
+use reqwest::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+    StatusCode, Url,
+};
+use std::{
+    convert::Infallible,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::{interval, sleep, Instant, MissedTickBehavior};
+
+use crate::{auth::Credentials, exposition::Format, network::Networks};
+
+/// How long to wait before retrying a failed push for the first time; the wait doubles after each
+/// further failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest to wait before retrying a failed push.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Where to push metrics, for hosts which Prometheus can't scrape.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// A Pushgateway, under the given job name.
+    Pushgateway { url: Url, job: String },
+    /// A Prometheus remote-write endpoint.
+    RemoteWrite(Url),
+}
+
+/// A pusher sending the gathered metrics to a target on a schedule, retrying failed pushes.
+#[derive(Debug, Clone)]
+pub struct Pusher {
+    /// Where to push.
+    target: Target,
+    /// How often to push.
+    interval: Duration,
+    /// Credentials for HTTP basic authentication to the target, if it requires them.
+    credentials: Option<Credentials>,
+    /// The HTTP client used to push.
+    http: reqwest::Client,
+}
+
+/// Why a push failed.
+enum Failure {
+    /// The failure may be transient, so the push is worth retrying.
+    Transient(eyre::Report),
+    /// The target rejected the push, so retrying it would fail again.
+    Permanent(eyre::Report),
+}
+
+impl From<reqwest::Error> for Failure {
+    fn from(error: reqwest::Error) -> Self {
+        Failure::Transient(error.into())
+    }
+}
+
+impl Pusher {
+    /// Make a pusher to the given target.
+    ///
+    /// A single attempt to push which takes longer than the interval is given up on, since the
+    /// next push would supersede it.
+    pub fn new(
+        target: Target,
+        interval: Duration,
+        credentials: Option<Credentials>,
+    ) -> eyre::Result<Self> {
+        Ok(Self {
+            target,
+            interval,
+            credentials,
+            http: reqwest::Client::builder().timeout(interval).build()?,
+        })
+    }
+
+    /// Push the metrics of every network to the target forever, at the pusher's interval.
+    ///
+    /// In on-demand mode, each push also updates the metrics, since nothing else may scrape them.
+    pub async fn push(self, networks: Networks) -> Infallible {
+        let mut ticks = interval(self.interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            networks.scrape().await;
+            if let Err(error) = self.export(&networks.gather()).await {
+                error!(destination = ?self.target, %error, "failed to push metrics");
+            }
+        }
+    }
+
+    /// Push the given metrics to the target, retrying with exponential backoff until the push
+    /// succeeds, the target rejects it, or the next push is due.
+    pub async fn export(&self, families: &[MetricFamily]) -> eyre::Result<()> {
+        let deadline = Instant::now() + self.interval;
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            match self.send(families).await {
+                Ok(()) => return Ok(()),
+                Err(Failure::Permanent(error)) => return Err(error),
+                Err(Failure::Transient(error)) => {
+                    // A push retried after the next one is due would only be superseded by it:
+                    if Instant::now() + backoff >= deadline {
+                        return Err(error);
+                    }
+                    warn!(destination = ?self.target, %error, ?backoff, "failed to push metrics, retrying");
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+
+    /// Make a single attempt to push the given metrics to the target.
+    async fn send(&self, families: &[MetricFamily]) -> Result<(), Failure> {
+        let request = match &self.target {
+            // Pushing with PUT replaces every metric previously pushed under the job, so that
+            // validators no longer monitored don't linger:
+            Target::Pushgateway { url, job } => {
+                let mut url = url.clone();
+                if let Ok(mut segments) = url.path_segments_mut() {
+                    segments
+                        .pop_if_empty()
+                        .extend(["metrics", "job", job.as_str()]);
+                }
+                let format = Format::Protobuf;
+                let body = format
+                    .encode(families, "")
+                    .map_err(|error| Failure::Permanent(error.into()))?;
+                self.http
+                    .put(url)
+                    .header(CONTENT_TYPE, format.content_type())
+                    .body(body)
+            }
+            Target::RemoteWrite(url) => {
+                let body = snap::raw::Encoder::new()
+                    .compress_vec(&write_request(families, SystemTime::now()).encode_to_vec())
+                    .map_err(|error| Failure::Permanent(error.into()))?;
+                self.http
+                    .post(url.clone())
+                    .header(CONTENT_TYPE, "application/x-protobuf")
+                    .header(CONTENT_ENCODING, "snappy")
+                    .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+                    .body(body)
+            }
+        };
+        let request = match &self.credentials {
+            Some(credentials) => {
+                request.basic_auth(credentials.user(), Some(credentials.password()))
+            }
+            None => request,
+        };
+
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let error = eyre!("{status}: {}", response.text().await.unwrap_or_default());
+        // Only server errors and rate limiting are worth retrying:
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            Err(Failure::Transient(error))
+        } else {
+            Err(Failure::Permanent(error))
+        }
+    }
+}
+
+/// Convert gathered metrics into a remote-write request, with every sample taken at `now`.
+///
+/// Nothing in umbrella is a histogram or summary, so those are skipped.
+pub fn write_request(families: &[MetricFamily], now: SystemTime) -> WriteRequest {
+    let timestamp = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    let mut timeseries = Vec::new();
+    for family in families {
+        for metric in family.get_metric() {
+            let value = match family.get_field_type() {
+                MetricType::GAUGE => metric.get_gauge().get_value(),
+                MetricType::COUNTER => metric.get_counter().get_value(),
+                MetricType::UNTYPED => metric.get_untyped().get_value(),
+                MetricType::HISTOGRAM | MetricType::SUMMARY => {
+                    warn!(name = %family.get_name(), "cannot remote-write histograms or summaries");
+                    break;
+                }
+            };
+            let mut labels = vec![Label {
+                name: "__name__".to_string(),
+                value: family.get_name().to_string(),
+            }];
+            labels.extend(metric.get_label().iter().map(|label| Label {
+                name: label.get_name().to_string(),
+                value: label.get_value().to_string(),
+            }));
+            // Remote write requires labels sorted by name:
+            labels.sort_by(|a, b| a.name.cmp(&b.name));
+            timeseries.push(TimeSeries {
+                labels,
+                samples: vec![Sample { value, timestamp }],
+            });
+        }
+    }
+    WriteRequest { timeseries }
+}
+
+/// A Prometheus remote-write request, as defined in `prometheus/prompb/remote.proto`.
+#[derive(Clone, PartialEq, Message)]
+pub struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub timeseries: Vec<TimeSeries>,
+}
+
+/// A single series of samples, as defined in `prometheus/prompb/types.proto`.
+#[derive(Clone, PartialEq, Message)]
+pub struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    pub labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    pub samples: Vec<Sample>,
+}
+
+/// A label of a series, as defined in `prometheus/prompb/types.proto`.
+#[derive(Clone, PartialEq, Message)]
+pub struct Label {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+/// A sample of a series, with its timestamp in milliseconds, as defined in
+/// `prometheus/prompb/types.proto`.
+#[derive(Clone, PartialEq, Message)]
+pub struct Sample {
+    #[prost(double, tag = "1")]
+    pub value: f64,
+    #[prost(int64, tag = "2")]
+    pub timestamp: i64,
+}
@@ -1,11 +1,164 @@
 use metrics::Unit;
-use penumbra_stake::validator;
+use prometheus::{
+    proto::{LabelPair, MetricFamily},
+    Registry,
+};
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::Arc, time::Duration};
 use tokio::time::Instant;
 
-use crate::Latest;
+use crate::{chain::Progress, Client, Latest};
 
-/// Emit Prometheus metrics for each piece of validator info.
-pub fn report(success: bool, last_update: Option<Instant>, info: &[Latest]) {
+/// A registry of metrics of its own, so that the metrics of each network monitored are recorded
+/// separately, and never collide with each other.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    recorder: Arc<metrics_prometheus::Recorder>,
+}
+
+impl Metrics {
+    /// Make a new, empty registry.
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let recorder = metrics_prometheus::Recorder::builder()
+            .with_registry(&registry)
+            .build();
+        Self {
+            registry,
+            recorder: Arc::new(recorder),
+        }
+    }
+
+    /// Record all the metrics emitted by the given function into this registry.
+    pub fn record<T>(&self, f: impl FnOnce() -> T) -> T {
+        metrics::with_local_recorder(&*self.recorder, f)
+    }
+
+    /// Gather all the metrics recorded into this registry.
+    pub fn gather(&self) -> Vec<MetricFamily> {
+        self.registry.gather()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+/// Merge the metrics gathered from several registries, combining families of the same name.
+pub fn merge(gathered: impl IntoIterator<Item = Vec<MetricFamily>>) -> Vec<MetricFamily> {
+    let mut merged: Vec<MetricFamily> = Vec::new();
+    for families in gathered {
+        for mut family in families {
+            match merged
+                .iter_mut()
+                .find(|existing| existing.get_name() == family.get_name())
+            {
+                Some(existing) => existing.mut_metric().extend(family.take_metric()),
+                None => merged.push(family),
+            }
+        }
+    }
+    merged.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+    merged
+}
+
+/// A constant label attached to every metric, given as `NAME=VALUE`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub name: String,
+    pub value: String,
+}
+
+impl Label {
+    /// Make a constant label, checking that its name is valid.
+    pub fn new(name: String, value: String) -> eyre::Result<Self> {
+        let mut chars = name.chars();
+        ensure!(
+            chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !name.starts_with("__"),
+            "invalid label name {name:?}"
+        );
+        Ok(Self { name, value })
+    }
+}
+
+impl FromStr for Label {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| eyre!("expected NAME=VALUE"))?;
+        Label::new(name.to_string(), value.to_string())
+    }
+}
+
+/// Check that a namespace is valid as a prefix of metric names.
+pub fn validate_namespace(namespace: &str) -> eyre::Result<()> {
+    let mut chars = namespace.chars();
+    ensure!(
+        chars
+            .next()
+            .map_or(true, |c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':'),
+        "invalid namespace {namespace:?}"
+    );
+    Ok(())
+}
+
+/// Prefix the name of every metric family with the namespace, and attach the constant labels to
+/// every metric.
+///
+/// Labels specific to a metric take precedence over constant labels of the same name, and
+/// constant labels with empty values are left out.
+pub fn decorate(families: &mut [MetricFamily], namespace: &str, labels: &BTreeMap<String, String>) {
+    for family in families.iter_mut() {
+        if !namespace.is_empty() {
+            let name = format!("{namespace}{}", family.get_name());
+            family.set_name(name);
+        }
+        for metric in family.mut_metric().iter_mut() {
+            for (name, value) in labels.iter().filter(|(_, value)| !value.is_empty()) {
+                if metric
+                    .get_label()
+                    .iter()
+                    .any(|label| label.get_name() == name)
+                {
+                    continue;
+                }
+                let mut label = LabelPair::new();
+                label.set_name(name.clone());
+                label.set_value(value.clone());
+                metric.mut_label().push(label);
+            }
+            metric
+                .mut_label()
+                .sort_by(|a, b| a.get_name().cmp(b.get_name()));
+        }
+    }
+}
+
+/// Emit Prometheus metrics for each node and each piece of validator info.
+pub fn report(
+    success: bool,
+    last_update: Option<Instant>,
+    total_voting_power: Option<u64>,
+    chain: &Progress,
+    halt_threshold: Duration,
+    node_sets: &[Vec<Client>],
+    info: &[Latest],
+) {
     gauge!("update_success").set(u8::from(success));
     describe_gauge!(
         "update_success",
@@ -23,43 +176,137 @@ pub fn report(success: bool, last_update: Option<Instant>, info: &[Latest]) {
         "Time elapsed in seconds since the last attempted update, whether or not it was successful",
     );
 
+    chain_progress(chain, halt_threshold);
+
+    for node in node_sets.iter().flatten() {
+        node_health(node);
+    }
+
     for latest in info.iter() {
-        validator_info(latest);
+        validator_info(latest, total_voting_power);
+    }
+}
+
+/// Emit Prometheus metrics for the progress of the chain itself.
+fn chain_progress(chain: &Progress, halt_threshold: Duration) {
+    if let Some(height) = chain.height {
+        gauge!("chain_height").set(height as f64);
+        describe_gauge!("chain_height", "Highest block height reported by any node",);
+    }
+
+    if let Some(block_time) = chain.average_block_time() {
+        gauge!("block_time_seconds").set(block_time);
+        describe_gauge!(
+            "block_time_seconds",
+            Unit::Seconds,
+            "Average time between recent blocks, according to their timestamps",
+        );
+    }
+
+    if let Some(stalled_for) = chain.stalled_for() {
+        gauge!("chain_stalled_seconds").set(stalled_for.as_secs_f64());
+        describe_gauge!(
+            "chain_stalled_seconds",
+            Unit::Seconds,
+            "Time elapsed in seconds since the chain height was last seen to advance on any node",
+        );
+    }
+
+    // If no node is reachable, whether the chain is halted is unknown, so keep the last reading:
+    if let Some(halted) = chain.is_halted(halt_threshold) {
+        gauge!("chain_halted").set(u8::from(halted));
+        describe_gauge!(
+            "chain_halted",
+            "Whether the chain height has stopped advancing across all reachable nodes for longer than the halt threshold (1) or not (0)",
+        );
+    }
+}
+
+/// Emit Prometheus metrics for the health of a single node.
+fn node_health(client: &Client) {
+    let node = client.uri().to_string();
+    let health = client.health();
+
+    // Nodes which have never been tried (e.g. fallbacks which were never needed) are not reported:
+    let Some(up) = health.up else {
+        return;
+    };
+
+    gauge!("node_up", "node" => node.clone()).set(u8::from(up));
+    describe_gauge!(
+        "node_up",
+        "Whether the node was reachable and answered every request in its most recent update round (1) or not (0)",
+    );
+
+    if let Some(height) = health.latest_height {
+        gauge!("node_latest_height", "node" => node.clone()).set(height as f64);
+        describe_gauge!(
+            "node_latest_height",
+            "Highest block height as of which the node has reported validator data",
+        );
+    }
+
+    if let Some(latency) = health.latency {
+        gauge!("node_rpc_latency_seconds", "node" => node.clone()).set(latency.as_secs_f64());
+        describe_gauge!(
+            "node_rpc_latency_seconds",
+            Unit::Seconds,
+            "Time taken by the most recent successful request to the node",
+        );
+    }
+
+    for (kind, count) in health.errors.iter() {
+        counter!("node_errors_total", "node" => node.clone(), "kind" => kind.as_str())
+            .absolute(*count);
     }
+    describe_counter!(
+        "node_errors_total",
+        Unit::Count,
+        "Total number of errors encountered with the node, by kind (connect, timeout, rpc, invalid)",
+    );
+}
+
+/// Get the labels of the `validator_info` metric of a validator: its identity key, its on-chain
+/// name and website if known, and its alias if it has one.
+pub fn info_labels(latest: &Latest) -> Vec<(&'static str, String)> {
+    let mut labels = vec![("validator", latest.identity().to_string())];
+    if let Some(definition) = latest.definition() {
+        labels.push(("name", definition.name));
+        labels.push(("website", definition.website));
+    }
+    if let Some(alias) = latest.alias() {
+        labels.push(("alias", alias.to_string()));
+    }
+    labels
 }
 
 /// Emit Prometheus metrics for a single piece of validator info.
-fn validator_info(latest: &Latest) {
+fn validator_info(latest: &Latest, total_voting_power: Option<u64>) {
     let validator = latest.identity();
-    let (Some(uptime), Some(state)) = (latest.uptime(), latest.state()) else {
+    let (Some(state), Some(state_number), Some(uptime_percent), Some(consecutive_missed_blocks)) = (
+        latest.state(),
+        latest.state_number(),
+        latest.uptime_percent(),
+        latest.consecutive_missed_blocks(),
+    ) else {
         // If any of the info is missing, don't emit any metrics for this validator:
         warn!(%validator, "missing information");
         return;
     };
 
-    let state_number = {
-        use validator::State::*;
-        match state {
-            Defined => 0,
-            Disabled => 1,
-            Inactive => 2,
-            Active => 3,
-            Jailed => 4,
-            Tombstoned => 5,
-        }
-    };
+    // Info-style metric carrying the human-readable names of the validator as labels:
+    gauge!("validator_info", &info_labels(latest)).set(1);
+    describe_gauge!(
+        "validator_info",
+        "Always 1, labeled with the validator's on-chain name and website, and operator-supplied alias (if any)",
+    );
+
     gauge!("state", "validator" => validator.to_string()).set(state_number);
     describe_gauge!(
         "state",
         "Validator state (0=Defined, 1=Disabled, 2=Inactive, 3=Active, 4=Jailed, 5=Tombstoned)",
     );
 
-    let uptime_percent = {
-        let downtime_fraction =
-            uptime.num_missed_blocks() as f64 / uptime.missed_blocks_window() as f64;
-        let uptime_fraction = 1.0 - downtime_fraction;
-        uptime_fraction * 100.0
-    };
     gauge!("uptime", "validator" => validator.to_string()).set(uptime_percent);
     describe_gauge!(
         "uptime",
@@ -67,18 +314,6 @@ fn validator_info(latest: &Latest) {
         "Validator uptime as a percentage, computed over the block window considered for on-chain uptime calculation",
     );
 
-    let consecutive_missed_blocks = {
-        let mut block = uptime.as_of_height() + 1;
-        uptime
-            .missed_blocks()
-            .rev()
-            .take_while(|&b| {
-                let consecutive = b + 1 == block;
-                block = b;
-                consecutive
-            })
-            .count()
-    };
     gauge!("consecutive_missed_blocks", "validator" => validator.to_string())
         .set(consecutive_missed_blocks as f64);
     describe_gauge!(
@@ -87,6 +322,129 @@ fn validator_info(latest: &Latest) {
         "Number of most-recent consecutive blocks missed by the validator (resets to 0 on a signed block)",
     );
 
+    if let (Some(voting_power), Some(bonding_state_number)) =
+        (latest.voting_power(), latest.bonding_state_number())
+    {
+        gauge!("voting_power", "validator" => validator.to_string()).set(voting_power as f64);
+        describe_gauge!(
+            "voting_power",
+            "Voting power of the validator, in units of the staking token",
+        );
+
+        // Only report the share when the total is known and meaningful:
+        if let Some(total) = total_voting_power.filter(|&total| total > 0) {
+            gauge!("voting_power_share", "validator" => validator.to_string())
+                .set(voting_power as f64 / total as f64);
+            describe_gauge!(
+                "voting_power_share",
+                "Fraction (0 to 1) of the total voting power of the active validator set held by the validator",
+            );
+        }
+
+        gauge!("bonding_state", "validator" => validator.to_string()).set(bonding_state_number);
+        describe_gauge!(
+            "bonding_state",
+            "Validator bonding state (0=Bonded, 1=Unbonding, 2=Unbonded)",
+        );
+
+        // When not unbonding, these are 0, so that a countdown never lingers after it's finished:
+        let unbonds_at_height = latest.unbonds_at_height().unwrap_or(0);
+        let as_of_height = latest.as_of_height().unwrap_or(0);
+        gauge!("unbonds_at_height", "validator" => validator.to_string())
+            .set(unbonds_at_height as f64);
+        describe_gauge!(
+            "unbonds_at_height",
+            "Height at which the validator's delegation pool finishes unbonding, or 0 if it is not unbonding",
+        );
+        gauge!("unbonding_blocks_remaining", "validator" => validator.to_string())
+            .set(unbonds_at_height.saturating_sub(as_of_height) as f64);
+        describe_gauge!(
+            "unbonding_blocks_remaining",
+            Unit::Count,
+            "Number of blocks until the validator's delegation pool finishes unbonding, or 0 if it is not unbonding",
+        );
+    }
+
+    if let (Some(exchange_rate), Some(reward_rate)) = (latest.exchange_rate(), latest.reward_rate())
+    {
+        gauge!("validator_exchange_rate", "validator" => validator.to_string()).set(exchange_rate);
+        describe_gauge!(
+            "validator_exchange_rate",
+            "Number of staking tokens one of the validator's delegation tokens is worth, as of the current epoch",
+        );
+
+        gauge!("validator_reward_rate", "validator" => validator.to_string()).set(reward_rate);
+        describe_gauge!(
+            "validator_reward_rate",
+            "Fractional reward rate of the validator's delegation pool for the current epoch, net of commission",
+        );
+    }
+
+    if let (Some(funding_streams), Some(commission_bps)) =
+        (latest.funding_streams(), latest.commission_bps())
+    {
+        gauge!("commission_bps", "validator" => validator.to_string()).set(commission_bps);
+        describe_gauge!(
+            "commission_bps",
+            "Total commission taken by the validator's funding streams, in basis points",
+        );
+
+        // A validator may have several streams to the same recipient, which share a series:
+        let mut rates_bps = BTreeMap::<String, u32>::new();
+        for (recipient, rate_bps) in funding_streams {
+            *rates_bps.entry(recipient).or_default() += u32::from(rate_bps);
+        }
+        for (recipient, rate_bps) in rates_bps {
+            gauge!(
+                "funding_stream_rate_bps",
+                "validator" => validator.to_string(),
+                "recipient" => recipient,
+            )
+            .set(rate_bps);
+        }
+        describe_gauge!(
+            "funding_stream_rate_bps",
+            "Commission taken by the validator's funding streams to each recipient address (or community-pool), in basis points",
+        );
+    }
+
+    counter!("downtime_incidents_total", "validator" => validator.to_string())
+        .absolute(latest.total_incidents());
+    describe_counter!(
+        "downtime_incidents_total",
+        Unit::Count,
+        "Total number of downtime incidents (runs of consecutive missed blocks) observed for the validator",
+    );
+
+    gauge!("node_disagreement", "validator" => validator.to_string())
+        .set(u8::from(latest.disagreement()));
+    describe_gauge!(
+        "node_disagreement",
+        "Whether nodes reported conflicting status or uptime for the validator as of the same height in the most recent update (1) or not (0)",
+    );
+
+    let penalties = latest.penalties();
+    if let Some(cumulative) = penalties.cumulative() {
+        gauge!("cumulative_penalty", "validator" => validator.to_string()).set(cumulative);
+        describe_gauge!(
+            "cumulative_penalty",
+            "Fraction (0 to 1) of the validator's delegation pool slashed over its whole history, compounded across epochs",
+        );
+
+        for (epoch, penalty) in penalties.applied() {
+            gauge!(
+                "penalty_applied",
+                "validator" => validator.to_string(),
+                "epoch" => epoch.to_string(),
+            )
+            .set(*penalty);
+        }
+        describe_gauge!(
+            "penalty_applied",
+            "Fraction (0 to 1) of the validator's delegation pool slashed in each epoch with a non-zero penalty since monitoring started",
+        );
+    }
+
     info!(
         %validator,
         %state,
@@ -1,28 +1,165 @@
-use axum::{extract::State, http::StatusCode, routing::get, Router};
-use std::{convert::Infallible, net::SocketAddr};
-use tokio::net::TcpListener;
+use axum::{
+    extract::State,
+    http::{
+        header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+        HeaderMap, StatusCode,
+    },
+    middleware,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use std::{convert::Infallible, net::SocketAddr, path::PathBuf};
+use tokio::{
+    net::TcpListener,
+    signal::unix::{signal, SignalKind},
+};
 
-use crate::App;
+use crate::{
+    api::{incidents_handler, validator_handler, validators_handler},
+    auth::{self, Auth},
+    exposition::Format,
+    network::Networks,
+};
 
-pub async fn serve(bind: SocketAddr, app: App) -> eyre::Result<Infallible> {
-    info!(%bind, "serving metrics");
-    // Use axum to serve metrics at the given address:
-    let router = Router::new()
+/// The settings for serving metrics, which only take effect on start.
+#[derive(Debug, Clone)]
+pub struct Server {
+    /// The address on which to serve.
+    pub bind: SocketAddr,
+    /// The certificate and key to serve HTTPS with, if not serving plain HTTP.
+    pub https: Option<Https>,
+    /// The credentials required to access everything but the health probes.
+    pub auth: Auth,
+}
+
+/// The PEM files of the certificate and key to serve HTTPS with.
+#[derive(Debug, Clone)]
+pub struct Https {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Serve metrics, health probes and the JSON API at the configured address, forever.
+pub async fn serve(server: Server, networks: Networks) -> eyre::Result<Infallible> {
+    let listener = std::net::TcpListener::bind(server.bind)?;
+    serve_on(listener, server.https, router(server.auth, networks)).await
+}
+
+/// Make the router serving metrics, health probes and the JSON API, requiring credentials for
+/// everything but the probes, which only go into detail about validators given credentials.
+pub fn router(auth: Auth, networks: Networks) -> Router {
+    let protected = Router::new()
         .route("/metrics", get(metrics_handler))
-        .with_state(app)
-        .into_make_service();
-    let listener = TcpListener::bind(bind).await?;
-    axum::serve(listener, router).await?;
-    unreachable!("axum::serve should never return without an error");
+        .route("/api/validators", get(validators_handler))
+        .route("/api/validators/:identity", get(validator_handler))
+        .route(
+            "/api/validators/:identity/incidents",
+            get(incidents_handler),
+        )
+        .route_layer(middleware::from_fn_with_state(auth.clone(), auth::require));
+    Router::new()
+        .route("/healthz", get(healthz_handler))
+        .route(
+            "/readyz",
+            get(move |state: State<Networks>, headers: HeaderMap| {
+                readyz_handler(state, headers, auth)
+            }),
+        )
+        .merge(protected)
+        .with_state(networks)
+}
+
+/// Serve the router on the given listener forever, over HTTPS if given a certificate and key.
+pub async fn serve_on(
+    listener: std::net::TcpListener,
+    https: Option<Https>,
+    router: Router,
+) -> eyre::Result<Infallible> {
+    let bind = listener.local_addr()?;
+    let router = router.into_make_service();
+    match https {
+        None => {
+            info!(%bind, "serving metrics");
+            listener.set_nonblocking(true)?;
+            axum::serve(TcpListener::from_std(listener)?, router).await?;
+        }
+        Some(Https { cert, key }) => {
+            info!(%bind, "serving metrics over HTTPS");
+            let config = RustlsConfig::from_pem_file(&cert, &key)
+                .await
+                .map_err(|error| eyre!("failed to load TLS certificate: {error}"))?;
+            tokio::spawn(reload_on_hangup(config.clone(), cert, key));
+            axum_server::from_tcp_rustls(listener, config)
+                .serve(router)
+                .await?;
+        }
+    }
+    unreachable!("the server should never return without an error");
+}
+
+/// Reload the TLS certificate and key whenever the process receives SIGHUP, so that renewed
+/// certificates are served without restarting.
+async fn reload_on_hangup(config: RustlsConfig, cert: PathBuf, key: PathBuf) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(error) => {
+            error!(%error, "failed to listen for SIGHUP, TLS certificate will not be reloaded");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        match config.reload_from_pem_file(&cert, &key).await {
+            Ok(()) => info!(cert = %cert.display(), "reloaded TLS certificate"),
+            Err(error) => {
+                error!(%error, "failed to reload TLS certificate, keeping previous certificate")
+            }
+        }
+    }
 }
 
-async fn metrics_handler(State(app): State<App>) -> axum::response::Result<String> {
-    app.update().await;
-    prometheus::TextEncoder::new()
-        .encode_to_string(&prometheus::default_registry().gather())
+/// Serve the metrics in the format the scraper prefers, according to its `Accept` header.
+async fn metrics_handler(
+    State(networks): State<Networks>,
+    headers: HeaderMap,
+) -> axum::response::Result<impl IntoResponse> {
+    networks.scrape().await;
+    let format = Format::negotiate(headers.get(ACCEPT).and_then(|accept| accept.to_str().ok()));
+    let body = format
+        .encode(&networks.gather(), &networks.namespace())
         .map_err(|e| {
             error!(%e, "failed to encode metrics");
             StatusCode::INTERNAL_SERVER_ERROR
-        })
-        .map_err(Into::into)
+        })?;
+    Ok(([(CONTENT_TYPE, format.content_type())], body))
+}
+
+/// Report that the process is alive, without doing anything else.
+async fn healthz_handler() -> &'static str {
+    "ok\n"
+}
+
+/// Report whether the application is ready to serve meaningful data, without querying any nodes.
+///
+/// The reasons it is not ready name validators, so they are only given with credentials.
+async fn readyz_handler(
+    State(networks): State<Networks>,
+    headers: HeaderMap,
+    auth: Auth,
+) -> (StatusCode, String) {
+    match networks.readiness() {
+        Ok(()) => (StatusCode::OK, "ready\n".to_string()),
+        Err(reasons) => {
+            let authorization = headers
+                .get(AUTHORIZATION)
+                .and_then(|header| header.to_str().ok());
+            let body = if auth.allows(authorization) {
+                reasons.into_iter().map(|reason| reason + "\n").collect()
+            } else {
+                "not ready\n".to_string()
+            };
+            (StatusCode::SERVICE_UNAVAILABLE, body)
+        }
+    }
 }
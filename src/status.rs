@@ -0,0 +1,189 @@
+use anstyle::{AnsiColor, Style};
+use penumbra_stake::validator;
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+use crate::{update, Latest, Options};
+
+/// Uptime percentage below which a validator's uptime is highlighted as a warning.
+const WARN_UPTIME: f64 = 99.0;
+
+/// Uptime percentage below which a validator's uptime is highlighted as critical.
+const CRIT_UPTIME: f64 = 95.0;
+
+/// The headings of the columns of the table.
+const HEADINGS: [&str; 7] = [
+    "NAME", "STATE", "BONDING", "UPTIME", "MISSED", "HEIGHT", "SOURCE",
+];
+
+/// Print the current status of each validator as a table, asking the nodes once, without needing
+/// Prometheus.
+#[derive(clap::Args, Clone, Debug)]
+pub struct Status {
+    #[clap(flatten)]
+    pub options: Options,
+    /// When to color the table.
+    #[clap(long, value_enum, default_value_t)]
+    pub color: Color,
+}
+
+/// When to color output.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+    /// Only if stdout is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    /// Always, even if stdout is not a terminal.
+    Always,
+    /// Never.
+    Never,
+}
+
+impl Color {
+    /// Check whether to color output.
+    fn enabled(&self) -> bool {
+        match self {
+            Color::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
+}
+
+impl Status {
+    /// Ask the nodes about each validator once, returning the table to print, with a table for
+    /// each network if there are several.
+    pub async fn run(self) -> eyre::Result<String> {
+        let options = self.options.clone().load().await?;
+        let color = self.color.enabled();
+        let networks = if options.networks.is_empty() {
+            vec![options]
+        } else {
+            options.networks
+        };
+
+        let mut output = String::new();
+        for (index, options) in networks.into_iter().enumerate() {
+            if let Some(network) = options.network.clone() {
+                if index > 0 {
+                    output.push('\n');
+                }
+                output.push_str(&paint(Style::new().bold(), network, color));
+                output.push('\n');
+            }
+            output.push_str(&table(&fetch(options).await, color));
+        }
+        Ok(output)
+    }
+}
+
+/// Get the latest info for each validator once, including any to be discovered from the chain.
+async fn fetch(options: Options) -> Vec<Latest> {
+    let mut targets = options.into_targets();
+    targets.rediscover().await;
+    update(&targets.node_sets, &targets.info, targets.connect_timeout).await;
+    targets.info
+}
+
+/// Format the latest info for each validator as a table with a row per validator, colored if
+/// asked to.
+///
+/// Anything no node reported is shown as `-`.
+pub fn table(info: &[Latest], color: bool) -> String {
+    let dash = || ("-".to_string(), Style::new());
+    let rows = info
+        .iter()
+        .map(|latest| {
+            let state = latest.state().map_or_else(dash, |state| {
+                let style = match state {
+                    validator::State::Active => AnsiColor::Green.on_default(),
+                    validator::State::Jailed | validator::State::Tombstoned => {
+                        AnsiColor::Red.on_default().bold()
+                    }
+                    _ => AnsiColor::Yellow.on_default(),
+                };
+                (state.to_string(), style)
+            });
+            let uptime = latest.uptime_percent().map_or_else(dash, |uptime| {
+                let style = if uptime < CRIT_UPTIME {
+                    AnsiColor::Red.on_default().bold()
+                } else if uptime < WARN_UPTIME {
+                    AnsiColor::Yellow.on_default()
+                } else {
+                    AnsiColor::Green.on_default()
+                };
+                (format!("{uptime:.2}%"), style)
+            });
+            let missed = latest
+                .consecutive_missed_blocks()
+                .map_or_else(dash, |missed| {
+                    let style = if missed > 0 {
+                        AnsiColor::Yellow.on_default()
+                    } else {
+                        Style::new()
+                    };
+                    (missed.to_string(), style)
+                });
+            let plain =
+                |value: Option<String>| value.map_or_else(dash, |value| (value, Style::new()));
+            [
+                (latest.name(), Style::new()),
+                state,
+                plain(latest.bonding_state().map(|state| state.to_string())),
+                uptime,
+                missed,
+                plain(latest.as_of_height().map(|height| height.to_string())),
+                plain(latest.source().map(|source| source.to_string())),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    // Pad the plain text of each cell, since the escape codes for colors take up no width:
+    let mut widths = HEADINGS.map(str::len);
+    for row in &rows {
+        for (width, (text, _)) in widths.iter_mut().zip(row) {
+            *width = (*width).max(text.chars().count());
+        }
+    }
+    let line = |cells: Vec<(String, Style)>| {
+        let last = cells.len() - 1;
+        let mut line = cells
+            .into_iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(index, ((text, style), width))| {
+                let padded = if index == last {
+                    text
+                } else {
+                    format!("{text:width$}")
+                };
+                paint(style, padded, color)
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        line.push('\n');
+        line
+    };
+
+    let mut output = line(
+        HEADINGS
+            .iter()
+            .map(|heading| (heading.to_string(), Style::new().bold()))
+            .collect(),
+    );
+    for row in rows {
+        output.push_str(&line(row.into()));
+    }
+    output
+}
+
+/// Apply a style to some text, if coloring.
+fn paint(style: Style, text: String, color: bool) -> String {
+    if color {
+        format!("{}{text}{}", style.render(), style.render_reset())
+    } else {
+        text
+    }
+}
@@ -0,0 +1,718 @@
+//! An in-process fake of the Penumbra stake query service, with scriptable responses, delays and
+//! failures, for driving umbrella end-to-end in tests, and fakes of the services umbrella pushes
+//! metrics to.
+
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, Method, StatusCode, Uri as HttpUri},
+    routing::{any, post},
+    Router,
+};
+use opentelemetry_proto::tonic::collector::metrics::v1::{
+    metrics_service_server::{MetricsService, MetricsServiceServer},
+    ExportMetricsServiceRequest, ExportMetricsServiceResponse,
+};
+use parking_lot::Mutex;
+use penumbra_proto::{
+    core::{
+        component::{
+            sct::v1::{
+                self as pb_sct,
+                query_service_server::{
+                    QueryService as SctQueryService, QueryServiceServer as SctQueryServiceServer,
+                },
+            },
+            stake::v1::{
+                self as pb,
+                query_service_server::{QueryService, QueryServiceServer},
+            },
+        },
+        keys::v1 as pb_keys,
+        num::v1 as pb_num,
+    },
+    util::tendermint_proxy::v1::{
+        self as pb_tm,
+        tendermint_proxy_service_server::{TendermintProxyService, TendermintProxyServiceServer},
+    },
+};
+use penumbra_stake::{validator, IdentityKey, Uptime};
+use prost::Message;
+use reqwest::Url;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::{net::TcpListener, time::sleep};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{
+    transport::{Identity, Server, ServerTlsConfig, Uri},
+    Request, Response, Status,
+};
+
+/// The number of blocks in each epoch of the fake chain.
+pub const EPOCH_LENGTH: u64 = 100;
+
+/// The time between blocks of the fake chain, in seconds.
+pub const BLOCK_TIME: i64 = 5;
+
+/// The chain ID of the fake chain.
+pub const CHAIN_ID: &str = "penumbra-fake";
+
+/// Make a distinct validator identity key from a single byte.
+pub fn identity(n: u8) -> IdentityKey {
+    pb_keys::IdentityKey { ik: vec![n; 32] }
+        .try_into()
+        .expect("any 32 bytes are a valid identity key")
+}
+
+/// Make an uptime record as of the given height, over the given window, in which exactly the given
+/// heights were missed.
+pub fn uptime(as_of_height: u64, window: usize, missed: &[u64]) -> Uptime {
+    let start = as_of_height.saturating_sub(window as u64);
+    let mut uptime = Uptime::new(start, window);
+    for height in start + 1..=as_of_height {
+        uptime
+            .mark_height_as_signed(height, !missed.contains(&height))
+            .expect("heights are marked in order");
+    }
+    uptime
+}
+
+/// A validator as served by the fake.
+#[derive(Debug, Clone)]
+pub struct FakeValidator {
+    pub identity: IdentityKey,
+    pub name: String,
+    pub state: validator::State,
+    pub bonding_state: validator::BondingState,
+    pub voting_power: u64,
+    pub uptime: Uptime,
+    pub exchange_rate: u64,
+    pub reward_rate: u64,
+    pub commission_bps: Vec<u16>,
+    pub penalties: BTreeMap<u64, f64>,
+}
+
+impl FakeValidator {
+    /// Make an active, bonded validator with the given identity, and perfect uptime as of the given
+    /// height.
+    pub fn new(n: u8, as_of_height: u64) -> Self {
+        Self {
+            identity: identity(n),
+            name: format!("validator-{n}"),
+            state: validator::State::Active,
+            bonding_state: validator::BondingState::Bonded,
+            voting_power: 1_000_000,
+            uptime: uptime(as_of_height, 100, &[]),
+            exchange_rate: 100_000_000,
+            reward_rate: 0,
+            commission_bps: Vec::new(),
+            penalties: BTreeMap::new(),
+        }
+    }
+
+    /// Set the uptime of the validator.
+    pub fn with_uptime(mut self, uptime: Uptime) -> Self {
+        self.uptime = uptime;
+        self
+    }
+
+    /// Set the state of the validator.
+    pub fn with_state(mut self, state: validator::State) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Set the bonding state of the validator.
+    pub fn with_bonding_state(mut self, bonding_state: validator::BondingState) -> Self {
+        self.bonding_state = bonding_state;
+        self
+    }
+
+    /// Set the voting power of the validator.
+    pub fn with_voting_power(mut self, voting_power: u64) -> Self {
+        self.voting_power = voting_power;
+        self
+    }
+
+    /// Set the exchange and reward rates of the validator, in fixed-point units of 10^-8.
+    pub fn with_rates(mut self, exchange_rate: u64, reward_rate: u64) -> Self {
+        self.exchange_rate = exchange_rate;
+        self.reward_rate = reward_rate;
+        self
+    }
+
+    /// Set the commission of the validator, as funding streams to the community pool with the given
+    /// rates in basis points.
+    pub fn with_commission(mut self, commission_bps: &[u16]) -> Self {
+        self.commission_bps = commission_bps.to_vec();
+        self
+    }
+
+    /// Set the penalty applied to the validator in the given epoch, as a fraction.
+    pub fn with_penalty(mut self, epoch: u64, penalty: f64) -> Self {
+        self.penalties.insert(epoch, penalty);
+        self
+    }
+
+    /// Get the compounded penalty over the given range of epochs, as a 128.128-bit fixed-point
+    /// number.
+    fn penalty(&self, start_epoch_index: u64, end_epoch_index: u64) -> pb::Penalty {
+        let kept = self
+            .penalties
+            .range(start_epoch_index..=end_epoch_index)
+            .map(|(_, penalty)| 1.0 - penalty)
+            .product::<f64>();
+        let fraction = ((1.0 - kept) * 2f64.powi(128)) as u128;
+        let mut inner = vec![0; 16];
+        inner.extend(fraction.to_be_bytes());
+        pb::Penalty { inner }
+    }
+
+    fn status(&self) -> pb::ValidatorStatus {
+        validator::Status {
+            identity_key: self.identity,
+            voting_power: self.voting_power.into(),
+            state: self.state,
+            bonding_state: self.bonding_state.clone(),
+        }
+        .into()
+    }
+
+    fn definition(&self) -> pb::Validator {
+        pb::Validator {
+            identity_key: Some(self.identity.into()),
+            // The compressed Ed25519 basepoint, which is a valid consensus key:
+            consensus_key: {
+                let mut key = vec![0x66; 32];
+                key[0] = 0x58;
+                key
+            },
+            // The decaf377 identity point, which is a valid governance key:
+            governance_key: Some(pb_keys::GovernanceKey { gk: vec![0; 32] }),
+            name: self.name.clone(),
+            website: format!("https://{}.example.com", self.name),
+            description: format!("The validator called {}", self.name),
+            enabled: true,
+            funding_streams: self
+                .commission_bps
+                .iter()
+                .map(|&rate_bps| pb::FundingStream {
+                    recipient: Some(pb::funding_stream::Recipient::ToCommunityPool(
+                        pb::funding_stream::ToCommunityPool {
+                            rate_bps: rate_bps.into(),
+                        },
+                    )),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn rate_data(&self) -> pb::RateData {
+        let amount = |lo| pb_num::Amount { lo, hi: 0 };
+        pb::RateData {
+            identity_key: Some(self.identity.into()),
+            validator_reward_rate: Some(amount(self.reward_rate)),
+            validator_exchange_rate: Some(amount(self.exchange_rate)),
+            ..Default::default()
+        }
+    }
+
+    fn info(&self) -> pb::ValidatorInfo {
+        pb::ValidatorInfo {
+            validator: Some(self.definition()),
+            status: Some(self.status()),
+            rate_data: Some(self.rate_data()),
+        }
+    }
+}
+
+/// A scriptable fake stake query service.
+///
+/// Clones share the same state, so a test can keep a handle to the fake after serving it and
+/// change its behavior between updates.
+#[derive(Debug, Clone, Default)]
+pub struct Fake {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    validators: Vec<FakeValidator>,
+    delay: Duration,
+    failing: bool,
+    without_definitions: bool,
+    without_epochs: bool,
+    without_status: bool,
+    epoch_requests: usize,
+    requests: usize,
+    height: Option<u64>,
+}
+
+impl Fake {
+    /// Make a new fake serving the given validators.
+    pub fn new(validators: impl IntoIterator<Item = FakeValidator>) -> Self {
+        let fake = Self::default();
+        for validator in validators {
+            fake.set(validator);
+        }
+        fake
+    }
+
+    /// Add a validator, or replace the validator with the same identity.
+    pub fn set(&self, validator: FakeValidator) {
+        let mut state = self.state.lock();
+        state
+            .validators
+            .retain(|v| v.identity != validator.identity);
+        state.validators.push(validator);
+    }
+
+    /// Remove the validator with the given identity.
+    pub fn remove(&self, identity: IdentityKey) {
+        self.state
+            .lock()
+            .validators
+            .retain(|v| v.identity != identity);
+    }
+
+    /// Set the height of the chain, as reported by the node's status; if it is never set, the
+    /// height is the highest height any validator's uptime is reported as of.
+    pub fn set_height(&self, height: u64) {
+        self.state.lock().height = Some(height);
+    }
+
+    /// Delay every response by the given duration.
+    pub fn set_delay(&self, delay: Duration) {
+        self.state.lock().delay = delay;
+    }
+
+    /// Make every request fail (or succeed again).
+    pub fn set_failing(&self, failing: bool) {
+        self.state.lock().failing = failing;
+    }
+
+    /// Make the node answer that it can't tell validator definitions (or tell them again), like a
+    /// node without the `GetValidatorInfo` method.
+    pub fn set_without_definitions(&self, without_definitions: bool) {
+        self.state.lock().without_definitions = without_definitions;
+    }
+
+    /// Make the node answer that it can't tell epochs (or tell them again), like a node without the
+    /// state commitment tree query service.
+    pub fn set_without_epochs(&self, without_epochs: bool) {
+        self.state.lock().without_epochs = without_epochs;
+    }
+
+    /// Make the node answer that it can't tell the status of the chain (or tell it again), like a
+    /// node without the proxy to its CometBFT RPC.
+    pub fn set_without_status(&self, without_status: bool) {
+        self.state.lock().without_status = without_status;
+    }
+
+    /// Get the number of requests for epochs received so far.
+    pub fn epoch_requests(&self) -> usize {
+        self.state.lock().epoch_requests
+    }
+
+    /// Get the number of requests received so far.
+    pub fn requests(&self) -> usize {
+        self.state.lock().requests
+    }
+
+    /// Serve the fake on an ephemeral port on localhost, returning the URI to connect to it.
+    ///
+    /// The server runs until the end of the test.
+    pub async fn serve(&self) -> Uri {
+        self.serve_with(Server::builder(), "http").await
+    }
+
+    /// Serve the fake over TLS on an ephemeral port on localhost, returning the URI to connect to
+    /// it.
+    ///
+    /// The fake presents the certificate for `localhost` and `127.0.0.1` in `tls/cert.pem`, which is
+    /// signed by the test CA in `tls/ca.pem`.
+    pub async fn serve_tls(&self) -> Uri {
+        let identity = Identity::from_pem(
+            include_bytes!("tls/cert.pem"),
+            include_bytes!("tls/key.pem"),
+        );
+        let server = Server::builder()
+            .tls_config(ServerTlsConfig::new().identity(identity))
+            .expect("TLS settings are valid");
+        self.serve_with(server, "https").await
+    }
+
+    /// Serve the fake with the given server on an ephemeral port on localhost, returning the URI
+    /// with the given scheme to connect to it.
+    async fn serve_with(&self, mut server: Server, scheme: &str) -> Uri {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("can bind to localhost");
+        let uri = format!("{scheme}://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        tokio::spawn(
+            server
+                .add_service(QueryServiceServer::new(self.clone()))
+                .add_service(SctQueryServiceServer::new(self.clone()))
+                .add_service(TendermintProxyServiceServer::new(self.clone()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        uri
+    }
+
+    /// Count the request, wait for the configured delay, and fail if configured to.
+    async fn begin(&self) -> Result<(), Status> {
+        let (delay, failing) = {
+            let mut state = self.state.lock();
+            state.requests += 1;
+            (state.delay, state.failing)
+        };
+        sleep(delay).await;
+        if failing {
+            return Err(Status::unavailable("fake is failing"));
+        }
+        Ok(())
+    }
+
+    /// Find the validator with the given identity.
+    fn find(&self, identity_key: Option<pb_keys::IdentityKey>) -> Result<FakeValidator, Status> {
+        let identity: IdentityKey = identity_key
+            .ok_or_else(|| Status::invalid_argument("missing identity key"))?
+            .try_into()
+            .map_err(|_| Status::invalid_argument("invalid identity key"))?;
+        self.state
+            .lock()
+            .validators
+            .iter()
+            .find(|v| v.identity == identity)
+            .cloned()
+            .ok_or_else(|| Status::not_found("no such validator"))
+    }
+}
+
+#[tonic::async_trait]
+impl QueryService for Fake {
+    async fn get_validator_info(
+        &self,
+        request: Request<pb::GetValidatorInfoRequest>,
+    ) -> Result<Response<pb::GetValidatorInfoResponse>, Status> {
+        self.begin().await?;
+        if self.state.lock().without_definitions {
+            return Err(Status::unimplemented("no validator info"));
+        }
+        let validator = self.find(request.into_inner().identity_key)?;
+        Ok(Response::new(pb::GetValidatorInfoResponse {
+            validator_info: Some(validator.info()),
+        }))
+    }
+
+    type ValidatorInfoStream =
+        tokio_stream::Iter<std::vec::IntoIter<Result<pb::ValidatorInfoResponse, Status>>>;
+
+    async fn validator_info(
+        &self,
+        request: Request<pb::ValidatorInfoRequest>,
+    ) -> Result<Response<Self::ValidatorInfoStream>, Status> {
+        self.begin().await?;
+        let show_inactive = request.into_inner().show_inactive;
+        let responses = self
+            .state
+            .lock()
+            .validators
+            .iter()
+            .filter(|v| show_inactive || v.state == validator::State::Active)
+            .map(|v| {
+                Ok(pb::ValidatorInfoResponse {
+                    validator_info: Some(v.info()),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Response::new(tokio_stream::iter(responses)))
+    }
+
+    async fn validator_status(
+        &self,
+        request: Request<pb::ValidatorStatusRequest>,
+    ) -> Result<Response<pb::ValidatorStatusResponse>, Status> {
+        self.begin().await?;
+        let validator = self.find(request.into_inner().identity_key)?;
+        Ok(Response::new(pb::ValidatorStatusResponse {
+            status: Some(validator.status()),
+        }))
+    }
+
+    async fn validator_penalty(
+        &self,
+        request: Request<pb::ValidatorPenaltyRequest>,
+    ) -> Result<Response<pb::ValidatorPenaltyResponse>, Status> {
+        self.begin().await?;
+        let request = request.into_inner();
+        let validator = self.find(request.identity_key)?;
+        Ok(Response::new(pb::ValidatorPenaltyResponse {
+            penalty: Some(validator.penalty(request.start_epoch_index, request.end_epoch_index)),
+        }))
+    }
+
+    async fn current_validator_rate(
+        &self,
+        request: Request<pb::CurrentValidatorRateRequest>,
+    ) -> Result<Response<pb::CurrentValidatorRateResponse>, Status> {
+        self.begin().await?;
+        let validator = self.find(request.into_inner().identity_key)?;
+        Ok(Response::new(pb::CurrentValidatorRateResponse {
+            data: Some(validator.rate_data()),
+        }))
+    }
+
+    async fn validator_uptime(
+        &self,
+        request: Request<pb::ValidatorUptimeRequest>,
+    ) -> Result<Response<pb::ValidatorUptimeResponse>, Status> {
+        self.begin().await?;
+        let validator = self.find(request.into_inner().identity_key)?;
+        Ok(Response::new(pb::ValidatorUptimeResponse {
+            uptime: Some(validator.uptime.into()),
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl SctQueryService for Fake {
+    async fn anchor_by_height(
+        &self,
+        _request: Request<pb_sct::AnchorByHeightRequest>,
+    ) -> Result<Response<pb_sct::AnchorByHeightResponse>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn epoch_by_height(
+        &self,
+        request: Request<pb_sct::EpochByHeightRequest>,
+    ) -> Result<Response<pb_sct::EpochByHeightResponse>, Status> {
+        self.begin().await?;
+        {
+            let mut state = self.state.lock();
+            state.epoch_requests += 1;
+            if state.without_epochs {
+                return Err(Status::unimplemented("no epochs"));
+            }
+        }
+        let index = request.into_inner().height / EPOCH_LENGTH;
+        Ok(Response::new(pb_sct::EpochByHeightResponse {
+            epoch: Some(pb_sct::Epoch {
+                index,
+                start_height: index * EPOCH_LENGTH,
+            }),
+        }))
+    }
+
+    async fn timestamp_by_height(
+        &self,
+        _request: Request<pb_sct::TimestampByHeightRequest>,
+    ) -> Result<Response<pb_sct::TimestampByHeightResponse>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+}
+
+#[tonic::async_trait]
+impl TendermintProxyService for Fake {
+    async fn get_tx(
+        &self,
+        _request: Request<pb_tm::GetTxRequest>,
+    ) -> Result<Response<pb_tm::GetTxResponse>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn broadcast_tx_async(
+        &self,
+        _request: Request<pb_tm::BroadcastTxAsyncRequest>,
+    ) -> Result<Response<pb_tm::BroadcastTxAsyncResponse>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn broadcast_tx_sync(
+        &self,
+        _request: Request<pb_tm::BroadcastTxSyncRequest>,
+    ) -> Result<Response<pb_tm::BroadcastTxSyncResponse>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn get_status(
+        &self,
+        _request: Request<pb_tm::GetStatusRequest>,
+    ) -> Result<Response<pb_tm::GetStatusResponse>, Status> {
+        self.begin().await?;
+        if self.state.lock().without_status {
+            return Err(Status::unimplemented("no status"));
+        }
+        let height = {
+            let state = self.state.lock();
+            state.height.unwrap_or_else(|| {
+                state
+                    .validators
+                    .iter()
+                    .map(|v| v.uptime.as_of_height())
+                    .max()
+                    .unwrap_or_default()
+            })
+        };
+
+        let mut sync_info = pb_tm::SyncInfo {
+            latest_block_height: height,
+            ..Default::default()
+        };
+        let block_time = sync_info.latest_block_time.insert(Default::default());
+        block_time.seconds = height as i64 * BLOCK_TIME;
+
+        Ok(Response::new(pb_tm::GetStatusResponse {
+            node_info: Some(penumbra_proto::tendermint::p2p::DefaultNodeInfo {
+                network: CHAIN_ID.to_string(),
+                ..Default::default()
+            }),
+            sync_info: Some(sync_info),
+            ..Default::default()
+        }))
+    }
+
+    async fn abci_query(
+        &self,
+        _request: Request<pb_tm::AbciQueryRequest>,
+    ) -> Result<Response<pb_tm::AbciQueryResponse>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+
+    async fn get_block_by_height(
+        &self,
+        _request: Request<pb_tm::GetBlockByHeightRequest>,
+    ) -> Result<Response<pb_tm::GetBlockByHeightResponse>, Status> {
+        Err(Status::unimplemented("not faked"))
+    }
+}
+
+/// A fake OpenTelemetry collector, accepting metrics pushed over OTLP/HTTP or OTLP/gRPC and
+/// remembering them.
+#[derive(Debug, Clone, Default)]
+pub struct FakeCollector {
+    requests: Arc<Mutex<Vec<ExportMetricsServiceRequest>>>,
+}
+
+impl FakeCollector {
+    /// Get every export request received so far.
+    pub fn requests(&self) -> Vec<ExportMetricsServiceRequest> {
+        self.requests.lock().clone()
+    }
+
+    /// Serve the collector on an ephemeral port on localhost, returning the URL to push to.
+    ///
+    /// The server runs until the end of the test.
+    pub async fn serve(&self) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("can bind to localhost");
+        let url = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let requests = self.requests.clone();
+        let router = Router::new().route(
+            "/v1/metrics",
+            post(move |body: Bytes| async move {
+                let request = ExportMetricsServiceRequest::decode(body)
+                    .map_err(|_| StatusCode::BAD_REQUEST)?;
+                requests.lock().push(request);
+                Ok::<_, StatusCode>(ExportMetricsServiceResponse::default().encode_to_vec())
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        url
+    }
+
+    /// Serve the collector over gRPC on an ephemeral port on localhost, returning the URL to push
+    /// to.
+    ///
+    /// The server runs until the end of the test.
+    pub async fn serve_grpc(&self) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("can bind to localhost");
+        let url = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(MetricsServiceServer::new(self.clone()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        url
+    }
+}
+
+#[tonic::async_trait]
+impl MetricsService for FakeCollector {
+    async fn export(
+        &self,
+        request: Request<ExportMetricsServiceRequest>,
+    ) -> Result<Response<ExportMetricsServiceResponse>, Status> {
+        self.requests.lock().push(request.into_inner());
+        Ok(Response::new(ExportMetricsServiceResponse::default()))
+    }
+}
+
+/// A request received by a [`FakeReceiver`].
+#[derive(Debug, Clone)]
+pub struct Received {
+    pub method: Method,
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+/// A fake HTTP endpoint standing in for a webhook, Pushgateway or remote-write receiver,
+/// remembering every request, and failing the first few.
+#[derive(Debug, Clone, Default)]
+pub struct FakeReceiver {
+    state: Arc<Mutex<(usize, Vec<Received>)>>,
+}
+
+impl FakeReceiver {
+    /// Fail the next given number of requests with `503 Service Unavailable`.
+    pub fn fail_next(&self, failures: usize) {
+        self.state.lock().0 = failures;
+    }
+
+    /// Get every request received so far, including those failed.
+    pub fn received(&self) -> Vec<Received> {
+        self.state.lock().1.clone()
+    }
+
+    /// Serve the receiver on an ephemeral port on localhost, returning its URL.
+    ///
+    /// The server runs until the end of the test.
+    pub async fn serve(&self) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("can bind to localhost");
+        let url = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let state = self.state.clone();
+        let router = Router::new().fallback(any(
+            move |method: Method, uri: HttpUri, headers: HeaderMap, body: Bytes| async move {
+                let mut state = state.lock();
+                state.1.push(Received {
+                    method,
+                    path: uri.path().to_string(),
+                    headers,
+                    body,
+                });
+                if state.0 > 0 {
+                    state.0 -= 1;
+                    StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    StatusCode::OK
+                }
+            },
+        ));
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        url
+    }
+}
@@ -0,0 +1,173 @@
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, PrivateKey, ServerName,
+};
+use serde::Deserialize;
+use std::{
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+use tokio::{fs, net::TcpStream};
+use tokio_rustls::TlsConnector;
+use tonic::{
+    codegen::http::uri::Scheme,
+    transport::{Channel, ClientTlsConfig, Endpoint, Identity, Uri},
+};
+
+/// TLS settings for connecting to a single node, for nodes behind a private CA, requiring a client
+/// certificate, or otherwise not reachable with the default settings.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Tls {
+    /// PEM file of CA certificates to trust for the node, as well as the usual web roots.
+    pub ca: Option<PathBuf>,
+    /// PEM file of the client certificate to present to the node, for mutual TLS.
+    pub cert: Option<PathBuf>,
+    /// PEM file of the private key of the client certificate.
+    pub key: Option<PathBuf>,
+    /// Server name to send and to verify the node's certificate against, if not the host of the
+    /// node's URI.
+    pub domain: Option<String>,
+    /// Skip verifying the node's certificate altogether: only ever use this for testing.
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+impl Tls {
+    /// Check that the settings are consistent, without reading any of the files.
+    pub fn validate(&self) -> eyre::Result<()> {
+        ensure!(
+            self.cert.is_some() == self.key.is_some(),
+            "a client certificate and its key must be given together"
+        );
+        Ok(())
+    }
+
+    /// Connect to a node with these settings.
+    ///
+    /// The certificate files are read anew on each connection, so that renewed certificates are
+    /// picked up without restarting.
+    pub async fn connect(&self, uri: &Uri) -> eyre::Result<Channel> {
+        ensure!(
+            uri.scheme_str() == Some("https"),
+            "TLS settings given for {uri}, which is not an https URI"
+        );
+        let domain = match &self.domain {
+            Some(domain) => domain.clone(),
+            None => uri
+                .host()
+                .ok_or_else(|| eyre!("no host in {uri}"))?
+                .to_string(),
+        };
+        let identity = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => Some((read(cert).await?, read(key).await?)),
+            _ => None,
+        };
+
+        if self.insecure {
+            return connect_insecure(uri, domain, identity).await;
+        }
+
+        let mut config = ClientTlsConfig::new().domain_name(domain);
+        if let Some(ca) = &self.ca {
+            config =
+                config.ca_certificate(tonic::transport::Certificate::from_pem(read(ca).await?));
+        }
+        if let Some((cert, key)) = identity {
+            config = config.identity(Identity::from_pem(cert, key));
+        }
+        Ok(Endpoint::from(uri.clone())
+            .tls_config(config)?
+            .connect()
+            .await?)
+    }
+}
+
+/// Read a certificate or key file, mentioning its path in any error.
+async fn read(path: &Path) -> eyre::Result<Vec<u8>> {
+    fs::read(path)
+        .await
+        .map_err(|error| eyre!("failed to read {}: {error}", path.display()))
+}
+
+/// Connect to a node over TLS without verifying its certificate.
+///
+/// Tonic always verifies certificates, so the TLS handshake is done by a custom connector instead,
+/// and tonic is handed an `http` URI so that it doesn't attempt a second handshake of its own.
+async fn connect_insecure(
+    uri: &Uri,
+    domain: String,
+    identity: Option<(Vec<u8>, Vec<u8>)>,
+) -> eyre::Result<Channel> {
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(NoVerification));
+    let mut config = match identity {
+        Some((cert, key)) => {
+            builder.with_client_auth_cert(certificates(&cert)?, private_key(&key)?)?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let connector = TlsConnector::from(Arc::new(config));
+    let server_name = ServerName::try_from(domain.as_str())?;
+
+    let mut parts = uri.clone().into_parts();
+    parts.scheme = Some(Scheme::HTTP);
+    let plain = Uri::from_parts(parts)?;
+
+    Ok(Endpoint::from(plain)
+        .connect_with_connector(tower::service_fn(move |uri: Uri| {
+            let connector = connector.clone();
+            let server_name = server_name.clone();
+            async move {
+                let host = uri.host().unwrap_or_default().to_string();
+                let port = uri.port_u16().unwrap_or(443);
+                let tcp = TcpStream::connect((host, port)).await?;
+                connector.connect(server_name, tcp).await
+            }
+        }))
+        .await?)
+}
+
+/// Parse the certificates in a PEM file.
+fn certificates(pem: &[u8]) -> eyre::Result<Vec<Certificate>> {
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(pem))?;
+    ensure!(
+        !certificates.is_empty(),
+        "no certificates in client certificate file"
+    );
+    Ok(certificates.into_iter().map(Certificate).collect())
+}
+
+/// Parse the first private key in a PEM file.
+fn private_key(pem: &[u8]) -> eyre::Result<PrivateKey> {
+    for item in rustls_pemfile::read_all(&mut BufReader::new(pem))? {
+        if let rustls_pemfile::Item::PKCS8Key(key)
+        | rustls_pemfile::Item::RSAKey(key)
+        | rustls_pemfile::Item::ECKey(key) = item
+        {
+            return Ok(PrivateKey(key));
+        }
+    }
+    Err(eyre!("no private key in client key file"))
+}
+
+/// A certificate verifier which accepts any certificate.
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
@@ -1,10 +1,15 @@
 use eyre::Ok;
-use penumbra_proto::core::component::stake::v1::{ValidatorStatusRequest, ValidatorUptimeRequest};
-use std::time::Duration;
-use tokio::{task::JoinSet, time::timeout};
+use penumbra_proto::core::component::stake::v1::{
+    self as pb, GetValidatorInfoRequest, ValidatorStatusRequest, ValidatorUptimeRequest,
+};
+use std::{collections::BTreeMap, time::Duration};
+use tokio::{
+    task::JoinSet,
+    time::{timeout, Instant},
+};
 use tonic::transport::Uri;
 
-use crate::{Client, Latest};
+use crate::{client::ErrorKind, Client, Latest};
 
 /// Use all the nodes in each set of nodes to update the info for each validator, treating each set
 /// concurrently, and stopping early if all the info is updated.
@@ -60,7 +65,7 @@ async fn update_all_validator_info(
 
     // Reconnect all the clients:
     for node in nodes.iter() {
-        node.connect().await.unwrap_or_else(|error| {
+        node.connect(connect_timeout).await.unwrap_or_else(|error| {
             warn!(node = %node.uri(), "{}", error);
         });
     }
@@ -81,6 +86,11 @@ async fn update_all_validator_info(
     // Wait for all the tasks to finish:
     while tasks.join_next().await.is_some() {}
 
+    // Compare what each node said about each validator:
+    for latest in info.iter() {
+        check_agreement(latest);
+    }
+
     // Check to make sure all the info was updated:
     info.iter()
         .filter(|latest| latest.is_stale())
@@ -97,9 +107,12 @@ async fn update_validator_info(client: Client, latest: Latest, connect_timeout:
     };
 
     let validator = latest.identity();
+    let start = Instant::now();
 
     // Async task that updates the info for a single validator from a single node, concurrently
-    // asking for its status and uptime:
+    // asking for its definition, status, uptime and rate data, and returning the height as of which
+    // the info was reported:
+    let node = client.uri().clone();
     let update = async move {
         let mut uptime_client = stake_client.clone();
         let uptime = async {
@@ -127,20 +140,95 @@ async fn update_validator_info(client: Client, latest: Latest, connect_timeout:
                 .try_into()
                 .map_err(|_| eyre!("invalid status data"))?)
         };
-        let (uptime, status) = join!(uptime, status);
+        let mut info_client = stake_client.clone();
+        let definition_and_rate_data = async {
+            let info = info_client
+                .get_validator_info(GetValidatorInfoRequest {
+                    identity_key: Some(validator.into()),
+                })
+                .await?
+                .into_inner()
+                .validator_info
+                .ok_or_else(|| eyre!("no validator info"))?;
+            let definition = info
+                .validator
+                .ok_or_else(|| eyre!("no validator definition"))?
+                .try_into()
+                .map_err(|_| eyre!("invalid validator definition"))?;
+            let rate_data = info
+                .rate_data
+                .ok_or_else(|| eyre!("no rate data"))?
+                .try_into()
+                .map_err(|_| eyre!("invalid rate data"))?;
+            Ok((definition, rate_data))
+        };
+        let (uptime, status, definition_and_rate_data) =
+            join!(uptime, status, definition_and_rate_data);
         let uptime = uptime?;
         let status = status?;
-        latest.update(status, uptime);
-        Ok(())
+        // Not every node can tell the definition and rate data, which are only used for reporting,
+        // so failing to get them doesn't fail the update:
+        let (definition, rate_data) = match definition_and_rate_data {
+            Result::Ok((definition, rate_data)) => (Some(definition), Some(rate_data)),
+            Err(error) => {
+                warn!(%node, %validator, %error, "failed to get validator definition");
+                (None, None)
+            }
+        };
+        let height = uptime.as_of_height();
+        latest.update(&node, definition, status, uptime, rate_data);
+        Ok(height)
     };
 
     // Set a timeout so we don't hang forever waiting for a response:
-    let update = async move { timeout(connect_timeout, update).await? };
+    let update = async { timeout(connect_timeout, update).await? };
 
     // If there was an error in the connection, throw it away and make the next update try to form a
     // new connection, rather than reusing the old, potentially broken one:
-    if let Err(error) = update.await {
-        client.disconnect();
-        warn!(node = %client.uri(), %validator, "{}", error);
+    match update.await {
+        Err(error) => {
+            client.disconnect();
+            client.record_error(ErrorKind::classify(&error));
+            warn!(node = %client.uri(), %validator, "{}", error);
+        }
+        Result::Ok(height) => client.record_success(start.elapsed(), height),
+    }
+}
+
+/// Compare the responses about a validator from each node which reported it as of the same height,
+/// recording whether any of them disagree, and logging which nodes said what.
+fn check_agreement(latest: &Latest) {
+    // Group the nodes by height, then by their response at that height:
+    let mut heights: BTreeMap<u64, Vec<((pb::ValidatorStatus, pb::Uptime), Vec<Uri>)>> =
+        BTreeMap::new();
+    for observation in latest.observations() {
+        let height = observation.uptime.as_of_height();
+        let response = (observation.status.into(), observation.uptime.into());
+        let responses = heights.entry(height).or_default();
+        match responses
+            .iter_mut()
+            .find(|(existing, _)| *existing == response)
+        {
+            Some((_, nodes)) => nodes.push(observation.node),
+            None => responses.push((response, vec![observation.node])),
+        }
+    }
+
+    let mut disagreement = false;
+    for (height, responses) in heights {
+        if responses.len() > 1 {
+            disagreement = true;
+            let groups = responses
+                .into_iter()
+                .map(|(_, nodes)| nodes)
+                .collect::<Vec<_>>();
+            warn!(
+                validator = %latest.identity(),
+                height,
+                ?groups,
+                "nodes disagree about the validator at the same height (each group of nodes agrees)"
+            );
+        }
     }
+    latest.set_disagreement(disagreement);
 }